@@ -0,0 +1,21 @@
+use std::{cell::RefCell, rc::Rc};
+
+thread_local! {
+    static SCRIPT_ARGS: RefCell<Rc<[String]>> = RefCell::new(Rc::from([]));
+}
+
+/// Sets the arguments given to the script after `--`, which kai code reads
+/// with the `argc` and `arg` natives of either backend
+pub fn set_script_args(args: Vec<String>) {
+    SCRIPT_ARGS.with(|script_args| *script_args.borrow_mut() = Rc::from(args));
+}
+
+/// Number of arguments given to the script
+pub fn script_arg_count() -> usize {
+    SCRIPT_ARGS.with(|script_args| script_args.borrow().len())
+}
+
+/// The script argument at `index`, counted from 0
+pub fn script_arg(index: usize) -> Option<String> {
+    SCRIPT_ARGS.with(|script_args| script_args.borrow().get(index).cloned())
+}
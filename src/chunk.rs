@@ -0,0 +1,195 @@
+use std::{fmt::Display, rc::Rc};
+
+use crate::token::Span;
+
+/// Instructions understood by the VM. Operands follow the opcode byte:
+/// constant indices and jump offsets take two bytes (big endian), local and
+/// upvalue slots and argument counts take one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum OpCode {
+    Constant,
+    Nil,
+    True,
+    False,
+    Pop,
+    GetLocal,
+    SetLocal,
+    GetGlobal,
+    DefineGlobal,
+    SetGlobal,
+    GetUpvalue,
+    SetUpvalue,
+    GetProperty,
+    SetProperty,
+    GetSuper,
+    Equal,
+    NotEqual,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+    Add,
+    /// Joins the string forms of the two values on top of the stack
+    Concat,
+    Subtract,
+    Multiply,
+    Divide,
+    Not,
+    Negate,
+    Print,
+    Jump,
+    JumpIfFalse,
+    Loop,
+    Call,
+    /// Followed by the function constant, then an `(is_local, index)` byte
+    /// pair for every upvalue the new closure captures
+    Closure,
+    CloseUpvalue,
+    Return,
+    Class,
+    Inherit,
+    Method,
+}
+
+impl OpCode {
+    /// Every opcode, indexed by its byte value
+    pub const ALL: [OpCode; 39] = [
+        OpCode::Constant,
+        OpCode::Nil,
+        OpCode::True,
+        OpCode::False,
+        OpCode::Pop,
+        OpCode::GetLocal,
+        OpCode::SetLocal,
+        OpCode::GetGlobal,
+        OpCode::DefineGlobal,
+        OpCode::SetGlobal,
+        OpCode::GetUpvalue,
+        OpCode::SetUpvalue,
+        OpCode::GetProperty,
+        OpCode::SetProperty,
+        OpCode::GetSuper,
+        OpCode::Equal,
+        OpCode::NotEqual,
+        OpCode::Greater,
+        OpCode::GreaterEqual,
+        OpCode::Less,
+        OpCode::LessEqual,
+        OpCode::Add,
+        OpCode::Concat,
+        OpCode::Subtract,
+        OpCode::Multiply,
+        OpCode::Divide,
+        OpCode::Not,
+        OpCode::Negate,
+        OpCode::Print,
+        OpCode::Jump,
+        OpCode::JumpIfFalse,
+        OpCode::Loop,
+        OpCode::Call,
+        OpCode::Closure,
+        OpCode::CloseUpvalue,
+        OpCode::Return,
+        OpCode::Class,
+        OpCode::Inherit,
+        OpCode::Method,
+    ];
+
+    pub fn from_byte(byte: u8) -> Option<OpCode> {
+        Self::ALL.get(byte as usize).copied()
+    }
+}
+
+/// Values known at compile time, stored in a chunk's constant pool
+#[derive(Debug, Clone)]
+pub enum Constant {
+    Integer(i64),
+    Number(f64),
+    String(Rc<str>),
+    Function(Rc<Prototype>),
+}
+
+impl PartialEq for Constant {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Constant::Integer(l), Constant::Integer(r)) => l == r,
+            // bitwise, so 0.0 and -0.0 stay apart
+            (Constant::Number(l), Constant::Number(r)) => l.to_bits() == r.to_bits(),
+            (Constant::String(l), Constant::String(r)) => l == r,
+            (Constant::Function(l), Constant::Function(r)) => Rc::ptr_eq(l, r),
+            _ => false,
+        }
+    }
+}
+
+impl Display for Constant {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Constant::Integer(val) => write!(f, "{}", val),
+            Constant::Number(val) => write!(f, "{:?}", val),
+            Constant::String(val) => write!(f, "{:?}", val),
+            Constant::Function(function) => write!(f, "<fn {}>", function.name),
+        }
+    }
+}
+
+/// A compiled sequence of instructions with the constants they refer to
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Chunk {
+    pub code: Vec<u8>,
+    pub constants: Vec<Constant>,
+    /// Source span of the instructions starting at each offset, an entry is
+    /// only added when the span changes
+    spans: Vec<(usize, Span)>,
+}
+
+impl Chunk {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn write(&mut self, byte: u8, span: Span) {
+        if self.spans.last().is_none_or(|(_, last)| *last != span) {
+            self.spans.push((self.code.len(), span));
+        }
+        self.code.push(byte);
+    }
+
+    pub fn write_op(&mut self, op: OpCode, span: Span) {
+        self.write(op as u8, span);
+    }
+
+    /// Adds `constant` to the pool, reusing an equal entry when there is one
+    pub fn add_constant(&mut self, constant: Constant) -> usize {
+        match self.constants.iter().position(|known| *known == constant) {
+            Some(index) => index,
+            None => {
+                self.constants.push(constant);
+                self.constants.len() - 1
+            }
+        }
+    }
+
+    pub fn read_u16(&self, offset: usize) -> u16 {
+        u16::from_be_bytes([self.code[offset], self.code[offset + 1]])
+    }
+
+    /// Source span of the instruction byte at `offset`
+    pub fn span_at(&self, offset: usize) -> Span {
+        let index = self.spans.partition_point(|(start, _)| *start <= offset);
+        index
+            .checked_sub(1)
+            .map_or_else(Span::default, |index| self.spans[index].1)
+    }
+}
+
+/// Compiled code of a function, shared by every closure created from it
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Prototype {
+    /// Empty for the top-level script
+    pub name: Rc<str>,
+    pub arity: usize,
+    pub upvalue_count: usize,
+    pub chunk: Chunk,
+}
@@ -0,0 +1,113 @@
+use std::{cell::RefCell, collections::HashMap, fmt::Debug, rc::Rc};
+
+use crate::{
+    function::{Callable, Function},
+    interpreter::{Interpreter, RuntimeError},
+    token::Token,
+    value::Value,
+};
+
+/// Runtime representation of a `class` declaration
+pub struct Class<'a> {
+    pub name: String,
+    superclass: Option<Rc<Class<'a>>>,
+    methods: HashMap<String, Rc<Function<'a>>>,
+}
+
+impl<'a> Class<'a> {
+    pub fn new(
+        name: &str,
+        superclass: Option<Rc<Class<'a>>>,
+        methods: HashMap<String, Rc<Function<'a>>>,
+    ) -> Self {
+        Class {
+            name: name.to_string(),
+            superclass,
+            methods,
+        }
+    }
+
+    /// Looks up a method on this class, then along the superclass chain
+    pub fn find_method(&self, name: &str) -> Option<Rc<Function<'a>>> {
+        self.methods.get(name).cloned().or_else(|| {
+            self.superclass
+                .as_ref()
+                .and_then(|superclass| superclass.find_method(name))
+        })
+    }
+}
+
+impl<'a> Debug for Class<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Class({})", self.name)
+    }
+}
+
+/// Calling a class creates a new instance and runs its `init` method
+impl<'a> Callable<'a> for Rc<Class<'a>> {
+    fn arity(&self) -> usize {
+        self.find_method("init")
+            .map_or(0, |initializer| initializer.arity())
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter<'a>,
+        arguments: Vec<Value<'a>>,
+    ) -> Result<Value<'a>, RuntimeError> {
+        let instance = Rc::new(RefCell::new(Instance::new(Rc::clone(self))));
+
+        if let Some(initializer) = self.find_method("init") {
+            initializer
+                .bind(Rc::clone(&instance))
+                .call(interpreter, arguments)?;
+        }
+
+        Ok(Value::Instance(instance))
+    }
+}
+
+/// An object created by calling a class
+pub struct Instance<'a> {
+    pub class: Rc<Class<'a>>,
+    fields: HashMap<String, Value<'a>>,
+}
+
+impl<'a> Instance<'a> {
+    pub fn new(class: Rc<Class<'a>>) -> Self {
+        Instance {
+            class,
+            fields: HashMap::new(),
+        }
+    }
+
+    /// Reads a field, falling back to a method bound to `instance`
+    pub fn get(
+        instance: &Rc<RefCell<Instance<'a>>>,
+        name: &Token,
+    ) -> Result<Value<'a>, RuntimeError> {
+        if let Some(value) = instance.borrow().fields.get(&*name.lexeme) {
+            return Ok(value.clone());
+        }
+
+        let method = instance.borrow().class.find_method(&name.lexeme);
+        match method {
+            Some(method) => Ok(Value::Function(Rc::new(method.bind(Rc::clone(instance))))),
+            None => Err(RuntimeError::UndefinedProperty {
+                name: name.lexeme.to_string(),
+                span: name.span,
+            }),
+        }
+    }
+
+    pub fn set(&mut self, name: &Token, value: Value<'a>) {
+        self.fields.insert(name.lexeme.to_string(), value);
+    }
+}
+
+// fields may point back at the instance, so only the class name is shown
+impl<'a> Debug for Instance<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Instance({})", self.class.name)
+    }
+}
@@ -0,0 +1,581 @@
+use std::{borrow::Cow, rc::Rc};
+
+use thiserror::Error;
+
+use crate::{
+    chunk::{Chunk, Constant, OpCode, Prototype},
+    expression::Expression,
+    statement::{ClassDecl, FunctionDecl, Stmt},
+    token::{Span, Token, TokenType},
+};
+
+/// Limits of the bytecode format that a program went over
+#[derive(Debug, Clone, Error, PartialEq)]
+pub enum CompileError {
+    #[error("Too many constants in one function")]
+    TooManyConstants { span: Span },
+    #[error("Too many local variables in one function")]
+    TooManyLocals { span: Span },
+    #[error("Too many closure variables in one function")]
+    TooManyUpvalues { span: Span },
+    #[error("Can't have more than 255 arguments")]
+    TooManyArguments { span: Span },
+    #[error("Too much code to jump over")]
+    JumpTooLarge { span: Span },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FunctionKind {
+    Script,
+    Function,
+    Method,
+    Initializer,
+}
+
+#[derive(Debug)]
+struct Local<'a> {
+    name: Cow<'a, str>,
+    depth: usize,
+    captured: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct UpvalueSlot {
+    index: u8,
+    is_local: bool,
+}
+
+/// Book-keeping for the function currently being compiled
+#[derive(Debug)]
+struct FunctionState<'a> {
+    prototype: Prototype,
+    kind: FunctionKind,
+    locals: Vec<Local<'a>>,
+    upvalues: Vec<UpvalueSlot>,
+    scope_depth: usize,
+}
+
+impl<'a> FunctionState<'a> {
+    fn new(name: &str, kind: FunctionKind) -> Self {
+        // slot 0 holds the callee, or `this` inside methods
+        let receiver = match kind {
+            FunctionKind::Method | FunctionKind::Initializer => "this",
+            FunctionKind::Script | FunctionKind::Function => "",
+        };
+
+        FunctionState {
+            prototype: Prototype {
+                name: Rc::from(name),
+                ..Prototype::default()
+            },
+            kind,
+            locals: vec![Local {
+                name: Cow::Borrowed(receiver),
+                depth: 0,
+                captured: false,
+            }],
+            upvalues: vec![],
+            scope_depth: 0,
+        }
+    }
+}
+
+/// Compiles a resolved program into bytecode for the [`crate::vm::Vm`]
+#[derive(Debug)]
+pub struct Compiler<'a> {
+    functions: Vec<FunctionState<'a>>,
+    errors: Vec<CompileError>,
+}
+
+impl<'a> Default for Compiler<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> Compiler<'a> {
+    pub fn new() -> Self {
+        Compiler {
+            functions: vec![FunctionState::new("", FunctionKind::Script)],
+            errors: vec![],
+        }
+    }
+
+    /// Compiles the top-level statements into the function run as the script
+    pub fn compile(mut self, statements: &[Stmt<'a>]) -> Result<Rc<Prototype>, Vec<CompileError>> {
+        for statement in statements {
+            self.statement(statement);
+        }
+        let end = statements.last().map_or_else(Span::default, Stmt::span);
+        self.emit_return(end);
+
+        let script = self.functions.pop().map(|state| state.prototype);
+        match script {
+            Some(script) if self.errors.is_empty() => Ok(Rc::new(script)),
+            _ => Err(self.errors),
+        }
+    }
+
+    fn statement(&mut self, statement: &Stmt<'a>) {
+        match statement {
+            Stmt::Expression(exp) => {
+                self.expression(exp);
+                self.emit(OpCode::Pop, exp.span());
+            }
+            Stmt::Print(_, exp) => {
+                self.expression(exp);
+                self.emit(OpCode::Print, statement.span());
+            }
+            Stmt::Var(name, initializer, _) => {
+                match initializer {
+                    Some(exp) => self.expression(exp),
+                    None => self.emit(OpCode::Nil, name.span),
+                }
+                self.define_variable(name);
+            }
+            Stmt::Block(statements, span) => {
+                self.begin_scope();
+                for statement in statements {
+                    self.statement(statement);
+                }
+                self.end_scope(*span);
+            }
+            Stmt::If(keyword, condition, then_branch, else_branch) => {
+                self.expression(condition);
+                let then_jump = self.emit_jump(OpCode::JumpIfFalse, keyword.span);
+                self.emit(OpCode::Pop, keyword.span);
+                self.statement(then_branch);
+
+                let else_jump = self.emit_jump(OpCode::Jump, keyword.span);
+                self.patch_jump(then_jump, keyword.span);
+                self.emit(OpCode::Pop, keyword.span);
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch);
+                }
+                self.patch_jump(else_jump, keyword.span);
+            }
+            Stmt::While(keyword, condition, body) => {
+                let loop_start = self.chunk().code.len();
+                self.expression(condition);
+                let exit_jump = self.emit_jump(OpCode::JumpIfFalse, keyword.span);
+                self.emit(OpCode::Pop, keyword.span);
+                self.statement(body);
+                self.emit_loop(loop_start, keyword.span);
+
+                self.patch_jump(exit_jump, keyword.span);
+                self.emit(OpCode::Pop, keyword.span);
+            }
+            Stmt::Function(declaration) => {
+                // declared up front so the body can refer to itself
+                let global = self.declare_variable(&declaration.name);
+                self.function(declaration, FunctionKind::Function);
+                self.finish_definition(&declaration.name, global);
+            }
+            Stmt::Class(declaration) => self.class(declaration),
+            Stmt::Return(keyword, value) => match value {
+                Some(value) => {
+                    self.expression(value);
+                    self.emit(OpCode::Return, keyword.span);
+                }
+                None => self.emit_return(keyword.span),
+            },
+        }
+    }
+
+    fn class(&mut self, declaration: &ClassDecl<'a>) {
+        let name = &declaration.name;
+        let name_constant = self.identifier_constant(name);
+
+        let global = self.declare_variable(name);
+        self.emit_with_u16(OpCode::Class, name_constant, name.span);
+        self.finish_definition(name, global);
+
+        let superclass = match &declaration.superclass {
+            Some(Expression::Variable(superclass, _)) => Some(superclass),
+            _ => None,
+        };
+        if let Some(superclass) = superclass {
+            self.named_variable(superclass, false);
+
+            // `super` lives in a scope wrapping the methods, which capture it
+            self.begin_scope();
+            self.add_local(Cow::Borrowed("super"), superclass.span);
+            self.named_variable(name, false);
+            self.emit(OpCode::Inherit, name.span);
+        }
+
+        self.named_variable(name, false);
+        for method in &declaration.methods {
+            let kind = if method.name.lexeme == "init" {
+                FunctionKind::Initializer
+            } else {
+                FunctionKind::Method
+            };
+            let method_constant = self.identifier_constant(&method.name);
+            self.function(method, kind);
+            self.emit_with_u16(OpCode::Method, method_constant, method.name.span);
+        }
+        self.emit(OpCode::Pop, name.span);
+
+        if superclass.is_some() {
+            self.end_scope(declaration.span);
+        }
+    }
+
+    /// Compiles a function body into its own prototype and emits the
+    /// instruction creating a closure over it
+    fn function(&mut self, declaration: &FunctionDecl<'a>, kind: FunctionKind) {
+        let span = declaration.name.span;
+        self.functions
+            .push(FunctionState::new(&declaration.name.lexeme, kind));
+        self.begin_scope();
+
+        for param in &declaration.params {
+            self.add_local(param.lexeme.clone(), param.span);
+        }
+        self.current().prototype.arity = declaration.params.len();
+
+        for statement in &declaration.body {
+            self.statement(statement);
+        }
+        self.emit_return(declaration.span);
+
+        let Some(state) = self.functions.pop() else {
+            return;
+        };
+        let mut prototype = state.prototype;
+        prototype.upvalue_count = state.upvalues.len();
+
+        let constant = self.make_constant(Constant::Function(Rc::new(prototype)), span);
+        self.emit_with_u16(OpCode::Closure, constant, span);
+        for upvalue in state.upvalues {
+            self.chunk().write(upvalue.is_local as u8, span);
+            self.chunk().write(upvalue.index, span);
+        }
+    }
+
+    fn expression(&mut self, expression: &Expression<'a>) {
+        match expression {
+            Expression::Literal(token) => match &token.token_type {
+                TokenType::Number(val) => self.emit_constant(Constant::Number(*val), token.span),
+                TokenType::Integer(val) => self.emit_constant(Constant::Integer(*val), token.span),
+                TokenType::String(val) => {
+                    self.emit_constant(Constant::String(Rc::from(val.as_ref())), token.span)
+                }
+                TokenType::True => self.emit(OpCode::True, token.span),
+                TokenType::False => self.emit(OpCode::False, token.span),
+                _ => self.emit(OpCode::Nil, token.span),
+            },
+            Expression::Grouping(exp, _) => self.expression(exp),
+            Expression::Unary(operator, right) => {
+                self.expression(right);
+                let op = match operator.token_type {
+                    TokenType::Minus => OpCode::Negate,
+                    _ => OpCode::Not,
+                };
+                self.emit(op, operator.span);
+            }
+            Expression::Binary(left, operator, right) => {
+                self.expression(left);
+                self.expression(right);
+                let op = match operator.token_type {
+                    TokenType::EqualEqual => OpCode::Equal,
+                    TokenType::NotEqual => OpCode::NotEqual,
+                    TokenType::Greater => OpCode::Greater,
+                    TokenType::GreaterEqual => OpCode::GreaterEqual,
+                    TokenType::Less => OpCode::Less,
+                    TokenType::LessEqual => OpCode::LessEqual,
+                    TokenType::Plus => OpCode::Add,
+                    TokenType::Minus => OpCode::Subtract,
+                    TokenType::Star => OpCode::Multiply,
+                    _ => OpCode::Divide,
+                };
+                self.emit(op, operator.span);
+            }
+            Expression::Logical(left, operator, right) => {
+                self.expression(left);
+                if operator.token_type == TokenType::Or {
+                    let else_jump = self.emit_jump(OpCode::JumpIfFalse, operator.span);
+                    let end_jump = self.emit_jump(OpCode::Jump, operator.span);
+                    self.patch_jump(else_jump, operator.span);
+                    self.emit(OpCode::Pop, operator.span);
+                    self.expression(right);
+                    self.patch_jump(end_jump, operator.span);
+                } else {
+                    let end_jump = self.emit_jump(OpCode::JumpIfFalse, operator.span);
+                    self.emit(OpCode::Pop, operator.span);
+                    self.expression(right);
+                    self.patch_jump(end_jump, operator.span);
+                }
+            }
+            Expression::Ternary(condition, then_exp, else_exp) => {
+                let span = expression.span();
+                self.expression(condition);
+                let then_jump = self.emit_jump(OpCode::JumpIfFalse, span);
+                self.emit(OpCode::Pop, span);
+                self.expression(then_exp);
+
+                let else_jump = self.emit_jump(OpCode::Jump, span);
+                self.patch_jump(then_jump, span);
+                self.emit(OpCode::Pop, span);
+                self.expression(else_exp);
+                self.patch_jump(else_jump, span);
+            }
+            Expression::Variable(name, _) => self.named_variable(name, false),
+            Expression::Assign(name, value, _) => {
+                self.expression(value);
+                self.named_variable(name, true);
+            }
+            Expression::Call(callee, paren, arguments) => {
+                self.expression(callee);
+                for argument in arguments {
+                    self.expression(argument);
+                }
+
+                let count = u8::try_from(arguments.len()).unwrap_or_else(|_| {
+                    self.errors
+                        .push(CompileError::TooManyArguments { span: paren.span });
+                    u8::MAX
+                });
+                self.emit(OpCode::Call, paren.span);
+                self.chunk().write(count, paren.span);
+            }
+            Expression::Get(object, name) => {
+                self.expression(object);
+                let constant = self.identifier_constant(name);
+                self.emit_with_u16(OpCode::GetProperty, constant, name.span);
+            }
+            Expression::Set(object, name, value) => {
+                self.expression(object);
+                self.expression(value);
+                let constant = self.identifier_constant(name);
+                self.emit_with_u16(OpCode::SetProperty, constant, name.span);
+            }
+            Expression::This(keyword, _) => self.named_variable(keyword, false),
+            Expression::Super(keyword, method, _) => {
+                let this = Token::new(TokenType::This, "this", keyword.span);
+                self.named_variable(&this, false);
+                self.named_variable(keyword, false);
+                let constant = self.identifier_constant(method);
+                self.emit_with_u16(OpCode::GetSuper, constant, method.span);
+            }
+            Expression::Interpolation(parts, span) => {
+                // parts come in threes or more, every one after the first is
+                // joined onto the string built so far
+                for (index, part) in parts.iter().enumerate() {
+                    self.expression(part);
+                    if index > 0 {
+                        self.emit(OpCode::Concat, *span);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Emits a read of `name`, or a write of the value on top of the stack
+    /// when `assign` is set, from a local, a captured variable or a global
+    fn named_variable(&mut self, name: &Token<'a>, assign: bool) {
+        let depth = self.functions.len() - 1;
+
+        if let Some(slot) = self.resolve_local(depth, &name.lexeme) {
+            let op = if assign {
+                OpCode::SetLocal
+            } else {
+                OpCode::GetLocal
+            };
+            self.emit(op, name.span);
+            self.chunk().write(slot, name.span);
+        } else if let Some(index) = self.resolve_upvalue(depth, name) {
+            let op = if assign {
+                OpCode::SetUpvalue
+            } else {
+                OpCode::GetUpvalue
+            };
+            self.emit(op, name.span);
+            self.chunk().write(index, name.span);
+        } else {
+            let op = if assign {
+                OpCode::SetGlobal
+            } else {
+                OpCode::GetGlobal
+            };
+            let constant = self.identifier_constant(name);
+            self.emit_with_u16(op, constant, name.span);
+        }
+    }
+
+    fn resolve_local(&self, function: usize, name: &str) -> Option<u8> {
+        self.functions[function]
+            .locals
+            .iter()
+            .rposition(|local| local.name == name)
+            .map(|slot| slot as u8)
+    }
+
+    fn resolve_upvalue(&mut self, function: usize, name: &Token<'a>) -> Option<u8> {
+        let enclosing = function.checked_sub(1)?;
+
+        if let Some(slot) = self.resolve_local(enclosing, &name.lexeme) {
+            self.functions[enclosing].locals[slot as usize].captured = true;
+            return Some(self.add_upvalue(function, slot, true, name.span));
+        }
+
+        let index = self.resolve_upvalue(enclosing, name)?;
+        Some(self.add_upvalue(function, index, false, name.span))
+    }
+
+    fn add_upvalue(&mut self, function: usize, index: u8, is_local: bool, span: Span) -> u8 {
+        let slot = UpvalueSlot { index, is_local };
+        let upvalues = &mut self.functions[function].upvalues;
+        if let Some(existing) = upvalues.iter().position(|upvalue| *upvalue == slot) {
+            return existing as u8;
+        }
+
+        if upvalues.len() > u8::MAX as usize {
+            self.errors.push(CompileError::TooManyUpvalues { span });
+            return 0;
+        }
+        upvalues.push(slot);
+        (upvalues.len() - 1) as u8
+    }
+
+    /// Reserves a local slot for `name` inside a scope, at the top level
+    /// returns the constant naming the global instead
+    fn declare_variable(&mut self, name: &Token<'a>) -> Option<u16> {
+        if self.current().scope_depth == 0 {
+            return Some(self.identifier_constant(name));
+        }
+        self.add_local(name.lexeme.clone(), name.span);
+        None
+    }
+
+    fn finish_definition(&mut self, name: &Token<'a>, global: Option<u16>) {
+        if let Some(constant) = global {
+            self.emit_with_u16(OpCode::DefineGlobal, constant, name.span);
+        }
+    }
+
+    /// Binds the value on top of the stack to `name`
+    fn define_variable(&mut self, name: &Token<'a>) {
+        let global = self.declare_variable(name);
+        self.finish_definition(name, global);
+    }
+
+    fn add_local(&mut self, name: Cow<'a, str>, span: Span) {
+        let state = self.current();
+        if state.locals.len() > u8::MAX as usize {
+            self.errors.push(CompileError::TooManyLocals { span });
+            return;
+        }
+
+        let depth = state.scope_depth;
+        state.locals.push(Local {
+            name,
+            depth,
+            captured: false,
+        });
+    }
+
+    fn begin_scope(&mut self) {
+        self.current().scope_depth += 1;
+    }
+
+    fn end_scope(&mut self, span: Span) {
+        let state = self.current();
+        state.scope_depth -= 1;
+
+        let depth = state.scope_depth;
+        let mut ops = vec![];
+        while let Some(local) = state.locals.last() {
+            if local.depth <= depth {
+                break;
+            }
+            ops.push(if local.captured {
+                OpCode::CloseUpvalue
+            } else {
+                OpCode::Pop
+            });
+            state.locals.pop();
+        }
+
+        for op in ops {
+            self.emit(op, span);
+        }
+    }
+
+    fn current(&mut self) -> &mut FunctionState<'a> {
+        let last = self.functions.len() - 1;
+        &mut self.functions[last]
+    }
+
+    fn chunk(&mut self) -> &mut Chunk {
+        &mut self.current().prototype.chunk
+    }
+
+    fn emit(&mut self, op: OpCode, span: Span) {
+        self.chunk().write_op(op, span);
+    }
+
+    fn emit_with_u16(&mut self, op: OpCode, operand: u16, span: Span) {
+        self.emit(op, span);
+        for byte in operand.to_be_bytes() {
+            self.chunk().write(byte, span);
+        }
+    }
+
+    /// Functions hand back `nil` unless they are initializers, which always
+    /// return the instance in slot 0
+    fn emit_return(&mut self, span: Span) {
+        if self.current().kind == FunctionKind::Initializer {
+            self.emit(OpCode::GetLocal, span);
+            self.chunk().write(0, span);
+        } else {
+            self.emit(OpCode::Nil, span);
+        }
+        self.emit(OpCode::Return, span);
+    }
+
+    fn make_constant(&mut self, constant: Constant, span: Span) -> u16 {
+        let index = self.chunk().add_constant(constant);
+        u16::try_from(index).unwrap_or_else(|_| {
+            self.errors.push(CompileError::TooManyConstants { span });
+            0
+        })
+    }
+
+    fn identifier_constant(&mut self, name: &Token<'a>) -> u16 {
+        self.make_constant(Constant::String(Rc::from(&*name.lexeme)), name.span)
+    }
+
+    fn emit_constant(&mut self, constant: Constant, span: Span) {
+        let index = self.make_constant(constant, span);
+        self.emit_with_u16(OpCode::Constant, index, span);
+    }
+
+    /// Emits a jump with a placeholder offset, returning where to patch it
+    fn emit_jump(&mut self, op: OpCode, span: Span) -> usize {
+        self.emit_with_u16(op, u16::MAX, span);
+        self.chunk().code.len() - 2
+    }
+
+    fn patch_jump(&mut self, offset: usize, span: Span) {
+        let jump = self.chunk().code.len() - offset - 2;
+        let jump = u16::try_from(jump).unwrap_or_else(|_| {
+            self.errors.push(CompileError::JumpTooLarge { span });
+            0
+        });
+
+        let code = &mut self.chunk().code;
+        code[offset..offset + 2].copy_from_slice(&jump.to_be_bytes());
+    }
+
+    fn emit_loop(&mut self, loop_start: usize, span: Span) {
+        // the offset also skips the operand of the loop instruction itself
+        let offset = self.chunk().code.len() + 3 - loop_start;
+        let offset = u16::try_from(offset).unwrap_or_else(|_| {
+            self.errors.push(CompileError::JumpTooLarge { span });
+            0
+        });
+        self.emit_with_u16(OpCode::Loop, offset, span);
+    }
+}
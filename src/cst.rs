@@ -0,0 +1,373 @@
+use rowan::{GreenNode, GreenNodeBuilder, Language};
+
+use crate::{
+    parser::{is_significant, Parser},
+    scanner::Scanner,
+    token::{Token, TokenType},
+};
+
+/// Kinds of the tokens and nodes of the concrete syntax tree. Token kinds
+/// mirror [`TokenType`] without the decoded values, which the tree keeps as
+/// source text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[repr(u16)]
+pub enum SyntaxKind {
+    // tokens
+    LeftParen,
+    RightParen,
+    LeftBrace,
+    RightBrace,
+    Comma,
+    Dot,
+    SemiColon,
+    Minus,
+    Plus,
+    Slash,
+    Star,
+    Question,
+    Colon,
+    Not,
+    NotEqual,
+    Equal,
+    EqualEqual,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+    Identifier,
+    String,
+    Interpolation,
+    InterpolationPart,
+    InterpolationEnd,
+    Number,
+    Integer,
+    And,
+    For,
+    If,
+    Else,
+    While,
+    Class,
+    Fun,
+    Nil,
+    Or,
+    False,
+    True,
+    Var,
+    Print,
+    Return,
+    This,
+    Super,
+    Eof,
+    // trivia
+    Whitespace,
+    NewLine,
+    Comment,
+    DocComment,
+    /// A lexeme that failed to scan, or a node wrapping tokens the parser
+    /// couldn't make sense of
+    Error,
+    // nodes
+    Program,
+    VarDecl,
+    FunDecl,
+    ClassDecl,
+    Superclass,
+    Method,
+    ParamList,
+    Block,
+    ExprStmt,
+    PrintStmt,
+    IfStmt,
+    WhileStmt,
+    ForStmt,
+    ReturnStmt,
+    Literal,
+    NameRef,
+    ThisExpr,
+    SuperExpr,
+    GroupExpr,
+    UnaryExpr,
+    BinaryExpr,
+    LogicalExpr,
+    TernaryExpr,
+    AssignExpr,
+    CallExpr,
+    ArgList,
+    FieldExpr,
+    InterpolatedString,
+}
+
+impl SyntaxKind {
+    pub const ALL: [SyntaxKind; 78] = [
+        SyntaxKind::LeftParen,
+        SyntaxKind::RightParen,
+        SyntaxKind::LeftBrace,
+        SyntaxKind::RightBrace,
+        SyntaxKind::Comma,
+        SyntaxKind::Dot,
+        SyntaxKind::SemiColon,
+        SyntaxKind::Minus,
+        SyntaxKind::Plus,
+        SyntaxKind::Slash,
+        SyntaxKind::Star,
+        SyntaxKind::Question,
+        SyntaxKind::Colon,
+        SyntaxKind::Not,
+        SyntaxKind::NotEqual,
+        SyntaxKind::Equal,
+        SyntaxKind::EqualEqual,
+        SyntaxKind::Greater,
+        SyntaxKind::GreaterEqual,
+        SyntaxKind::Less,
+        SyntaxKind::LessEqual,
+        SyntaxKind::Identifier,
+        SyntaxKind::String,
+        SyntaxKind::Interpolation,
+        SyntaxKind::InterpolationPart,
+        SyntaxKind::InterpolationEnd,
+        SyntaxKind::Number,
+        SyntaxKind::Integer,
+        SyntaxKind::And,
+        SyntaxKind::For,
+        SyntaxKind::If,
+        SyntaxKind::Else,
+        SyntaxKind::While,
+        SyntaxKind::Class,
+        SyntaxKind::Fun,
+        SyntaxKind::Nil,
+        SyntaxKind::Or,
+        SyntaxKind::False,
+        SyntaxKind::True,
+        SyntaxKind::Var,
+        SyntaxKind::Print,
+        SyntaxKind::Return,
+        SyntaxKind::This,
+        SyntaxKind::Super,
+        SyntaxKind::Eof,
+        SyntaxKind::Whitespace,
+        SyntaxKind::NewLine,
+        SyntaxKind::Comment,
+        SyntaxKind::DocComment,
+        SyntaxKind::Error,
+        SyntaxKind::Program,
+        SyntaxKind::VarDecl,
+        SyntaxKind::FunDecl,
+        SyntaxKind::ClassDecl,
+        SyntaxKind::Superclass,
+        SyntaxKind::Method,
+        SyntaxKind::ParamList,
+        SyntaxKind::Block,
+        SyntaxKind::ExprStmt,
+        SyntaxKind::PrintStmt,
+        SyntaxKind::IfStmt,
+        SyntaxKind::WhileStmt,
+        SyntaxKind::ForStmt,
+        SyntaxKind::ReturnStmt,
+        SyntaxKind::Literal,
+        SyntaxKind::NameRef,
+        SyntaxKind::ThisExpr,
+        SyntaxKind::SuperExpr,
+        SyntaxKind::GroupExpr,
+        SyntaxKind::UnaryExpr,
+        SyntaxKind::BinaryExpr,
+        SyntaxKind::LogicalExpr,
+        SyntaxKind::TernaryExpr,
+        SyntaxKind::AssignExpr,
+        SyntaxKind::CallExpr,
+        SyntaxKind::ArgList,
+        SyntaxKind::FieldExpr,
+        SyntaxKind::InterpolatedString,
+    ];
+
+    /// Whether tokens of this kind can appear anywhere without changing the
+    /// meaning of the program
+    pub fn is_trivia(self) -> bool {
+        matches!(
+            self,
+            SyntaxKind::Whitespace
+                | SyntaxKind::NewLine
+                | SyntaxKind::Comment
+                | SyntaxKind::DocComment
+        )
+    }
+}
+
+impl From<&TokenType<'_>> for SyntaxKind {
+    fn from(token_type: &TokenType) -> Self {
+        match token_type {
+            TokenType::LeftParen => SyntaxKind::LeftParen,
+            TokenType::RightParen => SyntaxKind::RightParen,
+            TokenType::LeftBrace => SyntaxKind::LeftBrace,
+            TokenType::RightBrace => SyntaxKind::RightBrace,
+            TokenType::Comma => SyntaxKind::Comma,
+            TokenType::Dot => SyntaxKind::Dot,
+            TokenType::SemiColon => SyntaxKind::SemiColon,
+            TokenType::Minus => SyntaxKind::Minus,
+            TokenType::Plus => SyntaxKind::Plus,
+            TokenType::Slash => SyntaxKind::Slash,
+            TokenType::Star => SyntaxKind::Star,
+            TokenType::Question => SyntaxKind::Question,
+            TokenType::Colon => SyntaxKind::Colon,
+            TokenType::NewLine => SyntaxKind::NewLine,
+            TokenType::DocComment => SyntaxKind::DocComment,
+            TokenType::Whitespace => SyntaxKind::Whitespace,
+            TokenType::Comment => SyntaxKind::Comment,
+            TokenType::Error => SyntaxKind::Error,
+            TokenType::Not => SyntaxKind::Not,
+            TokenType::NotEqual => SyntaxKind::NotEqual,
+            TokenType::Equal => SyntaxKind::Equal,
+            TokenType::EqualEqual => SyntaxKind::EqualEqual,
+            TokenType::Greater => SyntaxKind::Greater,
+            TokenType::GreaterEqual => SyntaxKind::GreaterEqual,
+            TokenType::Less => SyntaxKind::Less,
+            TokenType::LessEqual => SyntaxKind::LessEqual,
+            TokenType::Identifier => SyntaxKind::Identifier,
+            TokenType::String(_) => SyntaxKind::String,
+            TokenType::Interpolation(_) => SyntaxKind::Interpolation,
+            TokenType::InterpolationPart(_) => SyntaxKind::InterpolationPart,
+            TokenType::InterpolationEnd(_) => SyntaxKind::InterpolationEnd,
+            TokenType::Number(_) => SyntaxKind::Number,
+            TokenType::Integer(_) => SyntaxKind::Integer,
+            TokenType::And => SyntaxKind::And,
+            TokenType::For => SyntaxKind::For,
+            TokenType::If => SyntaxKind::If,
+            TokenType::Else => SyntaxKind::Else,
+            TokenType::While => SyntaxKind::While,
+            TokenType::Class => SyntaxKind::Class,
+            TokenType::Fun => SyntaxKind::Fun,
+            TokenType::Nil => SyntaxKind::Nil,
+            TokenType::Or => SyntaxKind::Or,
+            TokenType::False => SyntaxKind::False,
+            TokenType::True => SyntaxKind::True,
+            TokenType::Var => SyntaxKind::Var,
+            TokenType::Print => SyntaxKind::Print,
+            TokenType::Return => SyntaxKind::Return,
+            TokenType::This => SyntaxKind::This,
+            TokenType::Super => SyntaxKind::Super,
+            TokenType::Eof => SyntaxKind::Eof,
+        }
+    }
+}
+
+impl From<SyntaxKind> for rowan::SyntaxKind {
+    fn from(kind: SyntaxKind) -> Self {
+        rowan::SyntaxKind(kind as u16)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum KaiLanguage {}
+
+impl Language for KaiLanguage {
+    type Kind = SyntaxKind;
+
+    fn kind_from_raw(raw: rowan::SyntaxKind) -> SyntaxKind {
+        SyntaxKind::ALL[raw.0 as usize]
+    }
+
+    fn kind_to_raw(kind: SyntaxKind) -> rowan::SyntaxKind {
+        kind.into()
+    }
+}
+
+/// Red tree nodes: cheap handles into the green tree that know their parent
+/// and their offset in the source
+pub type SyntaxNode = rowan::SyntaxNode<KaiLanguage>;
+pub type SyntaxToken = rowan::SyntaxToken<KaiLanguage>;
+pub type SyntaxElement = rowan::SyntaxElement<KaiLanguage>;
+
+/// What [`Parser`] reports as it parses, for the concrete syntax tree to be
+/// built from. Positions are indices into the tokens the grammar sees, see
+/// [`is_significant`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Event {
+    /// Opens a node of this kind at the token at this position
+    Start(SyntaxKind, usize),
+    /// Closes the last node opened, after the tokens before this position
+    Finish(usize),
+}
+
+/// Parses `source` into a concrete syntax tree holding every character of
+/// it, trivia included, so `parse(source).to_string() == source`. The tree
+/// is built from the events of the same [`Parser`] that builds the AST, so
+/// both agree on every statement. Parsing never fails: a declaration with a
+/// syntax error ends up in an [`SyntaxKind::Error`] node along with the
+/// tokens skipped to recover from it, and the errors themselves are left to
+/// the scanner and the parser to report.
+pub fn parse(source: &str) -> SyntaxNode {
+    let mut scanner = Scanner::new(source).lossless();
+    let _ = scanner.scan_tokens();
+
+    let mut parser = Parser::new(scanner.tokens.clone());
+    let _ = parser.parse();
+
+    let mut builder = TreeBuilder {
+        tokens: &scanner.tokens,
+        next: 0,
+        consumed: 0,
+        builder: GreenNodeBuilder::new(),
+    };
+    SyntaxNode::new_root(builder.build(&parser.events))
+}
+
+/// Replays parser events over the lossless token stream. Trivia is attached
+/// to the innermost node open when the token after it is added, so nodes
+/// start at their first significant token.
+struct TreeBuilder<'t, 'a> {
+    tokens: &'t [Token<'a>],
+    /// Index of the next token to add
+    next: usize,
+    /// How many significant tokens were added so far
+    consumed: usize,
+    builder: GreenNodeBuilder<'static>,
+}
+
+impl<'t, 'a> TreeBuilder<'t, 'a> {
+    fn build(&mut self, events: &[Event]) -> GreenNode {
+        self.builder.start_node(SyntaxKind::Program.into());
+        for event in events {
+            match *event {
+                Event::Start(kind, position) => {
+                    self.tokens_before(position);
+                    self.trivia();
+                    self.builder.start_node(kind.into());
+                }
+                Event::Finish(position) => {
+                    self.tokens_before(position);
+                    self.builder.finish_node();
+                }
+            }
+        }
+        self.trivia();
+        self.builder.finish_node();
+
+        std::mem::take(&mut self.builder).finish()
+    }
+
+    /// Adds the tokens up to the significant one at `position`
+    fn tokens_before(&mut self, position: usize) {
+        while self.consumed < position {
+            self.trivia();
+            self.token();
+            self.consumed += 1;
+        }
+    }
+
+    /// Adds the tokens the grammar doesn't see, up to the next one it does
+    fn trivia(&mut self) {
+        while self
+            .tokens
+            .get(self.next)
+            .is_some_and(|token| !is_significant(token))
+        {
+            self.token();
+        }
+    }
+
+    fn token(&mut self) {
+        let token = &self.tokens[self.next];
+        self.builder
+            .token(SyntaxKind::from(&token.token_type).into(), &token.lexeme);
+        self.next += 1;
+    }
+}
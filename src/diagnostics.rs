@@ -0,0 +1,373 @@
+use std::fmt::{Display, Write};
+
+use yansi::{Color, Style};
+
+use crate::{
+    compiler::CompileError,
+    formatter::FormatError,
+    interpreter::RuntimeError,
+    parser::ParseError,
+    repl::CommandError,
+    resolver::{ResolveError, ResolveWarning},
+    scanner::ScannerError,
+    token::Span,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    fn color(self) -> Color {
+        match self {
+            Severity::Error => Color::Red,
+            Severity::Warning => Color::Yellow,
+        }
+    }
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// A problem found in a kai program, ready to be rendered against its source
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: String,
+    pub span: Option<Span>,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn error(code: &'static str, message: impl Display) -> Self {
+        Diagnostic {
+            severity: Severity::Error,
+            code,
+            message: message.to_string(),
+            span: None,
+            notes: vec![],
+        }
+    }
+
+    pub fn warning(code: &'static str, message: impl Display) -> Self {
+        Diagnostic {
+            severity: Severity::Warning,
+            ..Self::error(code, message)
+        }
+    }
+
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = Some(span);
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Display) -> Self {
+        self.notes.push(note.to_string());
+        self
+    }
+}
+
+/// Errors and warnings that can be reported as a [`Diagnostic`]
+pub trait ToDiagnostic {
+    fn to_diagnostic(&self) -> Diagnostic;
+}
+
+impl ToDiagnostic for ScannerError {
+    fn to_diagnostic(&self) -> Diagnostic {
+        match self {
+            Self::InvalidCharacter { span, .. } => {
+                Diagnostic::error("E0001", self).with_span(*span)
+            }
+            Self::UnterminatedString { span } => Diagnostic::error("E0002", self)
+                .with_span(*span)
+                .with_note("help: close the string with '\"'"),
+            Self::UnterminatedComment { span } => Diagnostic::error("E0003", self)
+                .with_span(*span)
+                .with_note("help: close the comment with '*/'"),
+            Self::MalformedNumber { span, .. } => Diagnostic::error("E0004", self)
+                .with_span(*span)
+                .with_note("note: numbers look like 42, 1_000, 0xFF, 0b1010, 0o17, 2.5 or 1e-9"),
+            Self::IntegerOutOfRange { span, .. } => Diagnostic::error("E0008", self)
+                .with_span(*span)
+                .with_note("help: write it as a float, like 1e20"),
+            Self::UnterminatedInterpolation { span } => Diagnostic::error("E0007", self)
+                .with_span(*span)
+                .with_note("help: close the interpolation with '}'"),
+            Self::InvalidEscape { span, .. } => Diagnostic::error("E0005", self)
+                .with_span(*span)
+                .with_note("note: supported escapes are \\n, \\t, \\\", \\\\, \\$ and \\u{...}"),
+            Self::InvalidUnicodeEscape { span, .. } => Diagnostic::error("E0006", self)
+                .with_span(*span)
+                .with_note("note: write one to six hex digits naming a unicode scalar value, like \\u{1F600}"),
+        }
+    }
+}
+
+impl ToDiagnostic for ParseError {
+    fn to_diagnostic(&self) -> Diagnostic {
+        match self {
+            Self::UnexpectedToken { span, .. } => Diagnostic::error("E0101", self).with_span(*span),
+            Self::ExpectedExpression { span, .. } => {
+                Diagnostic::error("E0102", self).with_span(*span)
+            }
+            Self::InvalidAssignmentTarget { span } => Diagnostic::error("E0103", self)
+                .with_span(*span)
+                .with_note("note: only variables and properties can be assigned to"),
+        }
+    }
+}
+
+impl ToDiagnostic for ResolveError {
+    fn to_diagnostic(&self) -> Diagnostic {
+        let (code, span) = match self {
+            Self::ReadInOwnInitializer { span, .. } => ("E0201", span),
+            Self::DuplicateLocal { span, .. } => ("E0202", span),
+            Self::TopLevelReturn { span } => ("E0203", span),
+            Self::ReturnFromInitializer { span } => ("E0204", span),
+            Self::ThisOutsideClass { span } => ("E0205", span),
+            Self::SuperOutsideClass { span } => ("E0206", span),
+            Self::SuperWithoutSuperclass { span } => ("E0207", span),
+            Self::InheritFromSelf { span } => ("E0208", span),
+        };
+        let diagnostic = Diagnostic::error(code, self).with_span(*span);
+
+        match self {
+            Self::ReadInOwnInitializer { .. } => {
+                diagnostic.with_note("help: declare the variable under a different name")
+            }
+            Self::ReturnFromInitializer { .. } => {
+                diagnostic.with_note("note: 'init' always returns the new instance")
+            }
+            _ => diagnostic,
+        }
+    }
+}
+
+impl ToDiagnostic for ResolveWarning {
+    fn to_diagnostic(&self) -> Diagnostic {
+        match self {
+            Self::UnusedLocal { span, .. } => Diagnostic::warning("W0201", self)
+                .with_span(*span)
+                .with_note("help: remove the variable if it is not needed"),
+        }
+    }
+}
+
+impl ToDiagnostic for RuntimeError {
+    fn to_diagnostic(&self) -> Diagnostic {
+        let span = match self {
+            Self::OperandMustBeNumber { span, .. }
+            | Self::OperandsMustBeNumbers { span, .. }
+            | Self::InvalidAddition { span }
+            | Self::UndefinedVariable { span, .. }
+            | Self::NotCallable { span }
+            | Self::ArityMismatch { span, .. }
+            | Self::UndefinedProperty { span, .. }
+            | Self::NotAnInstance { span }
+            | Self::SuperclassNotAClass { span }
+            | Self::ThisOutsideClass { span }
+            | Self::SuperOutsideSubclass { span }
+            | Self::IntegerOverflow { span, .. }
+            | Self::DivisionByZero { span }
+            | Self::StackOverflow { span }
+            | Self::Interrupted { span } => Some(*span),
+            Self::Output(_) => None,
+        };
+        let code = match self {
+            Self::OperandMustBeNumber { .. } => "E0301",
+            Self::OperandsMustBeNumbers { .. } => "E0302",
+            Self::InvalidAddition { .. } => "E0303",
+            Self::UndefinedVariable { .. } => "E0304",
+            Self::NotCallable { .. } => "E0305",
+            Self::ArityMismatch { .. } => "E0306",
+            Self::UndefinedProperty { .. } => "E0307",
+            Self::NotAnInstance { .. } => "E0308",
+            Self::SuperclassNotAClass { .. } => "E0309",
+            Self::ThisOutsideClass { .. } => "E0310",
+            Self::SuperOutsideSubclass { .. } => "E0311",
+            Self::Output(_) => "E0312",
+            Self::StackOverflow { .. } => "E0313",
+            Self::IntegerOverflow { .. } => "E0314",
+            Self::DivisionByZero { .. } => "E0315",
+            Self::Interrupted { .. } => "E0316",
+        };
+
+        let diagnostic = match self {
+            Self::IntegerOverflow { .. } => Diagnostic::error(code, self)
+                .with_note("note: integers are 64 bits, write a float like 1.0 to go beyond"),
+            _ => Diagnostic::error(code, self),
+        };
+        match span {
+            Some(span) => diagnostic.with_span(span),
+            None => diagnostic,
+        }
+    }
+}
+
+impl ToDiagnostic for CompileError {
+    fn to_diagnostic(&self) -> Diagnostic {
+        let (code, span) = match self {
+            Self::TooManyConstants { span } => ("E0401", span),
+            Self::TooManyLocals { span } => ("E0402", span),
+            Self::TooManyUpvalues { span } => ("E0403", span),
+            Self::TooManyArguments { span } => ("E0404", span),
+            Self::JumpTooLarge { span } => ("E0405", span),
+        };
+        Diagnostic::error(code, self)
+            .with_span(*span)
+            .with_note("note: this is a limit of the bytecode backend, try running without --vm")
+    }
+}
+
+impl ToDiagnostic for CommandError {
+    fn to_diagnostic(&self) -> Diagnostic {
+        let code = match self {
+            Self::Unknown { .. } => "E0501",
+            Self::MissingArgument { .. } => "E0502",
+        };
+        Diagnostic::error(code, self).with_note("help: type :help to list the commands")
+    }
+}
+
+impl ToDiagnostic for FormatError {
+    fn to_diagnostic(&self) -> Diagnostic {
+        let code = match self {
+            Self::ChangedProgram => "E0601",
+        };
+        Diagnostic::error(code, self)
+            .with_note("note: the source was left untouched, this is a bug in the formatter")
+    }
+}
+
+/// Renders diagnostics rustc style, quoting the offending line of `source`
+/// and underlining the span
+///
+/// ```text
+/// error[E0304]: Undefined variable 'count'
+///  --> script.kai:2:7
+///   |
+/// 2 | print count;
+///   |       ^^^^^
+/// ```
+#[derive(Debug, Clone)]
+pub struct Renderer<'s> {
+    file_name: &'s str,
+    source: &'s str,
+    color: bool,
+}
+
+impl<'s> Renderer<'s> {
+    pub fn new(file_name: &'s str, source: &'s str) -> Self {
+        Renderer {
+            file_name,
+            source,
+            color: true,
+        }
+    }
+
+    /// Turns ANSI colors on or off, off is meant for logs and pipes
+    pub fn with_color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn render(&self, diagnostic: &Diagnostic) -> String {
+        let accent = Style::new(diagnostic.severity.color()).bold();
+        let gutter_style = Style::new(Color::Blue).bold();
+
+        let mut out = String::new();
+        let header = format!("{}[{}]", diagnostic.severity, diagnostic.code);
+        let _ = writeln!(
+            out,
+            "{}{} {}",
+            self.paint(accent, header),
+            self.paint(Style::default().bold(), ":"),
+            self.paint(Style::default().bold(), &diagnostic.message)
+        );
+
+        // a default span, as given to code the compiler made up, has no line
+        // to quote
+        let Some(span) = diagnostic.span.filter(|span| span.line > 0) else {
+            let _ = writeln!(
+                out,
+                " {} {}",
+                self.paint(gutter_style, "-->"),
+                self.file_name
+            );
+            self.render_notes(&mut out, diagnostic, "", gutter_style);
+            return out;
+        };
+
+        let line_number = span.line.to_string();
+        let padding = " ".repeat(line_number.len());
+        let source_line = self.source.lines().nth(span.line - 1).unwrap_or_default();
+
+        // only the first line of a multi-line span is underlined, tabs are
+        // kept in the indent so the carets line up with the quoted source
+        let offset = span.column - 1;
+        let indent = source_line
+            .chars()
+            .take(offset)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect::<String>();
+        let width = self
+            .source
+            .get(span.start..span.end)
+            .and_then(|text| text.lines().next())
+            .map_or(0, |text| text.chars().count())
+            .min(source_line.chars().count().saturating_sub(offset))
+            .max(1);
+
+        let _ = writeln!(
+            out,
+            "{}{} {}:{}:{}",
+            padding,
+            self.paint(gutter_style, "-->"),
+            self.file_name,
+            span.line,
+            span.column
+        );
+        let _ = writeln!(out, "{} {}", padding, self.paint(gutter_style, "|"));
+        let _ = writeln!(
+            out,
+            "{} {} {}",
+            self.paint(gutter_style, &line_number),
+            self.paint(gutter_style, "|"),
+            source_line
+        );
+        let _ = writeln!(
+            out,
+            "{} {} {}{}",
+            padding,
+            self.paint(gutter_style, "|"),
+            indent,
+            self.paint(accent, "^".repeat(width))
+        );
+        self.render_notes(&mut out, diagnostic, &padding, gutter_style);
+
+        out
+    }
+
+    fn render_notes(&self, out: &mut String, diagnostic: &Diagnostic, padding: &str, style: Style) {
+        for note in &diagnostic.notes {
+            let _ = writeln!(out, "{} {} {}", padding, self.paint(style, "="), note);
+        }
+    }
+
+    fn paint(&self, style: Style, item: impl Display) -> String {
+        if self.color {
+            style.paint(item).to_string()
+        } else {
+            item.to_string()
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use std::fmt::Write;
+
+use crate::chunk::{Chunk, Constant, OpCode, Prototype};
+
+/// Renders `prototype` and every function nested in its constant pool as a
+/// human readable listing, one instruction per line:
+///
+/// ```text
+/// == <script> ==
+/// constants:
+///     0  1
+///     1  "a"
+/// code:
+/// 0000     1 Constant            0  1
+/// 0003     | DefineGlobal        1  "a"
+/// ```
+///
+/// The second column is the source line, `|` when it didn't change.
+pub fn disassemble(prototype: &Prototype) -> String {
+    let mut out = String::new();
+    disassemble_into(&mut out, prototype);
+    out
+}
+
+fn disassemble_into(out: &mut String, prototype: &Prototype) {
+    let name = if prototype.name.is_empty() {
+        "<script>".to_string()
+    } else {
+        format!("<fn {}>", prototype.name)
+    };
+    // writing into a String can't fail
+    let _ = writeln!(out, "== {} ==", name);
+
+    let chunk = &prototype.chunk;
+    if !chunk.constants.is_empty() {
+        let _ = writeln!(out, "constants:");
+        for (index, constant) in chunk.constants.iter().enumerate() {
+            let _ = writeln!(out, "{:5}  {}", index, constant);
+        }
+    }
+
+    let _ = writeln!(out, "code:");
+    let mut offset = 0;
+    while offset < chunk.code.len() {
+        offset = disassemble_instruction(out, chunk, offset);
+    }
+
+    for constant in &chunk.constants {
+        if let Constant::Function(function) = constant {
+            let _ = writeln!(out);
+            disassemble_into(out, function);
+        }
+    }
+}
+
+/// Writes the instruction at `offset`, returning the offset of the next one
+pub fn disassemble_instruction(out: &mut String, chunk: &Chunk, offset: usize) -> usize {
+    let _ = write!(out, "{:04} ", offset);
+    let line = chunk.span_at(offset).line;
+    if offset > 0 && chunk.span_at(offset - 1).line == line {
+        let _ = write!(out, "    | ");
+    } else {
+        let _ = write!(out, "{:5} ", line);
+    }
+
+    let op = match OpCode::from_byte(chunk.code[offset]) {
+        Some(op) => op,
+        None => {
+            let _ = writeln!(out, "Unknown opcode {}", chunk.code[offset]);
+            return offset + 1;
+        }
+    };
+
+    match op {
+        OpCode::Constant
+        | OpCode::GetGlobal
+        | OpCode::DefineGlobal
+        | OpCode::SetGlobal
+        | OpCode::GetProperty
+        | OpCode::SetProperty
+        | OpCode::GetSuper
+        | OpCode::Class
+        | OpCode::Method => {
+            let index = chunk.read_u16(offset + 1) as usize;
+            let _ = writeln!(
+                out,
+                "{:<16} {:4}  {}",
+                format!("{:?}", op),
+                index,
+                chunk.constants[index]
+            );
+            offset + 3
+        }
+        OpCode::GetLocal
+        | OpCode::SetLocal
+        | OpCode::GetUpvalue
+        | OpCode::SetUpvalue
+        | OpCode::Call => {
+            let slot = chunk.code[offset + 1];
+            let _ = writeln!(out, "{:<16} {:4}", format!("{:?}", op), slot);
+            offset + 2
+        }
+        OpCode::Jump | OpCode::JumpIfFalse | OpCode::Loop => {
+            let jump = chunk.read_u16(offset + 1) as usize;
+            let target = if op == OpCode::Loop {
+                (offset + 3).saturating_sub(jump)
+            } else {
+                offset + 3 + jump
+            };
+            let _ = writeln!(
+                out,
+                "{:<16} {:4} -> {:04}",
+                format!("{:?}", op),
+                offset,
+                target
+            );
+            offset + 3
+        }
+        OpCode::Closure => {
+            let index = chunk.read_u16(offset + 1) as usize;
+            let constant = &chunk.constants[index];
+            let _ = writeln!(out, "{:<16} {:4}  {}", format!("{:?}", op), index, constant);
+
+            let mut offset = offset + 3;
+            if let Constant::Function(function) = constant {
+                for _ in 0..function.upvalue_count {
+                    let kind = if chunk.code[offset] == 1 {
+                        "local"
+                    } else {
+                        "upvalue"
+                    };
+                    let index = chunk.code[offset + 1];
+                    let _ = writeln!(out, "{:04}    |   {} {}", offset, kind, index);
+                    offset += 2;
+                }
+            }
+            offset
+        }
+        _ => {
+            let _ = writeln!(out, "{:?}", op);
+            offset + 1
+        }
+    }
+}
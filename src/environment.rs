@@ -0,0 +1,93 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use crate::{interpreter::RuntimeError, token::Token, value::Value};
+
+/// Storage for variable bindings, chained to the enclosing scope
+#[derive(Debug, Default)]
+pub struct Environment<'a> {
+    values: HashMap<String, Value<'a>>,
+    enclosing: Option<Rc<RefCell<Environment<'a>>>>,
+}
+
+impl<'a> Environment<'a> {
+    pub fn new() -> Self {
+        Environment {
+            values: HashMap::new(),
+            enclosing: None,
+        }
+    }
+
+    /// Creates a nested scope whose lookups fall back to `enclosing`
+    pub fn with_enclosing(enclosing: Rc<RefCell<Environment<'a>>>) -> Self {
+        Environment {
+            values: HashMap::new(),
+            enclosing: Some(enclosing),
+        }
+    }
+
+    /// Names bound in this scope, not counting enclosing ones
+    pub fn names(&self) -> Vec<String> {
+        self.values.keys().cloned().collect()
+    }
+
+    pub fn define(&mut self, name: &str, value: Value<'a>) {
+        self.values.insert(name.to_string(), value);
+    }
+
+    pub fn get(&self, name: &Token) -> Result<Value<'a>, RuntimeError> {
+        self.lookup(&name.lexeme)
+            .ok_or_else(|| RuntimeError::undefined_variable(name))
+    }
+
+    /// Finds `name` in this scope or any enclosing one
+    pub fn lookup(&self, name: &str) -> Option<Value<'a>> {
+        if let Some(value) = self.values.get(name) {
+            return Some(value.clone());
+        }
+
+        self.enclosing
+            .as_ref()
+            .and_then(|enclosing| enclosing.borrow().lookup(name))
+    }
+
+    /// Reads `name` from the scope `depth` levels up the chain
+    pub fn get_at(&self, depth: usize, name: &str) -> Option<Value<'a>> {
+        if depth == 0 {
+            return self.values.get(name).cloned();
+        }
+
+        self.enclosing
+            .as_ref()
+            .and_then(|enclosing| enclosing.borrow().get_at(depth - 1, name))
+    }
+
+    /// Overwrites `name` in the scope `depth` levels up the chain
+    pub fn assign_at(
+        &mut self,
+        depth: usize,
+        name: &Token,
+        value: Value<'a>,
+    ) -> Result<(), RuntimeError> {
+        if depth == 0 {
+            self.values.insert(name.lexeme.to_string(), value);
+            return Ok(());
+        }
+
+        match &self.enclosing {
+            Some(enclosing) => enclosing.borrow_mut().assign_at(depth - 1, name, value),
+            None => Err(RuntimeError::undefined_variable(name)),
+        }
+    }
+
+    pub fn assign(&mut self, name: &Token, value: Value<'a>) -> Result<(), RuntimeError> {
+        if let Some(slot) = self.values.get_mut(&*name.lexeme) {
+            *slot = value;
+            return Ok(());
+        }
+
+        match &self.enclosing {
+            Some(enclosing) => enclosing.borrow_mut().assign(name, value),
+            None => Err(RuntimeError::undefined_variable(name)),
+        }
+    }
+}
@@ -1,18 +1,92 @@
-use std::fmt::{format, Display};
+use std::{cell::Cell, fmt::Display};
 
-use crate::token::TokenType;
+use crate::token::{Span, Token};
+
+/// Number of scopes between a variable use and its declaration, filled in by the
+/// resolver. `None` means the variable is global.
+pub type Depth = Cell<Option<usize>>;
 
 #[derive(Debug, PartialEq)]
 pub enum Expression<'a> {
-    Binary(Box<Expression<'a>>, TokenType<'a>, Box<Expression<'a>>),
-    Unary(TokenType<'a>, Box<Expression<'a>>),
-    Grouping(Box<Expression<'a>>),
-    Literal(TokenType<'a>),
+    Binary(Box<Expression<'a>>, Token<'a>, Box<Expression<'a>>),
+    Logical(Box<Expression<'a>>, Token<'a>, Box<Expression<'a>>),
+    Unary(Token<'a>, Box<Expression<'a>>),
+    Grouping(Box<Expression<'a>>, Span),
+    Literal(Token<'a>),
+    Variable(Token<'a>, Depth),
+    Assign(Token<'a>, Box<Expression<'a>>, Depth),
     Ternary(
         Box<Expression<'a>>,
         Box<Expression<'a>>,
         Box<Expression<'a>>,
     ),
+    Call(Box<Expression<'a>>, Token<'a>, Vec<Expression<'a>>),
+    Get(Box<Expression<'a>>, Token<'a>),
+    Set(Box<Expression<'a>>, Token<'a>, Box<Expression<'a>>),
+    This(Token<'a>, Depth),
+    Super(Token<'a>, Token<'a>, Depth),
+    /// String literals alternating with interpolated expressions, starting
+    /// and ending with a literal
+    Interpolation(Vec<Expression<'a>>, Span),
+}
+
+impl<'a> Expression<'a> {
+    /// A copy owning all its text, so it can outlive the source
+    pub fn to_static(&self) -> Expression<'static> {
+        let boxed = |exp: &Expression<'a>| Box::new(exp.to_static());
+        let depth = |depth: &Depth| Cell::new(depth.get());
+        match self {
+            Self::Binary(left, operator, right) => {
+                Expression::Binary(boxed(left), operator.to_static(), boxed(right))
+            }
+            Self::Logical(left, operator, right) => {
+                Expression::Logical(boxed(left), operator.to_static(), boxed(right))
+            }
+            Self::Unary(operator, right) => Expression::Unary(operator.to_static(), boxed(right)),
+            Self::Grouping(exp, span) => Expression::Grouping(boxed(exp), *span),
+            Self::Literal(token) => Expression::Literal(token.to_static()),
+            Self::Variable(name, scope) => Expression::Variable(name.to_static(), depth(scope)),
+            Self::Assign(name, value, scope) => {
+                Expression::Assign(name.to_static(), boxed(value), depth(scope))
+            }
+            Self::Ternary(condition, then_exp, else_exp) => {
+                Expression::Ternary(boxed(condition), boxed(then_exp), boxed(else_exp))
+            }
+            Self::Call(callee, paren, arguments) => Expression::Call(
+                boxed(callee),
+                paren.to_static(),
+                arguments.iter().map(Expression::to_static).collect(),
+            ),
+            Self::Get(object, name) => Expression::Get(boxed(object), name.to_static()),
+            Self::Set(object, name, value) => {
+                Expression::Set(boxed(object), name.to_static(), boxed(value))
+            }
+            Self::This(keyword, scope) => Expression::This(keyword.to_static(), depth(scope)),
+            Self::Super(keyword, method, scope) => {
+                Expression::Super(keyword.to_static(), method.to_static(), depth(scope))
+            }
+            Self::Interpolation(parts, span) => {
+                Expression::Interpolation(parts.iter().map(Expression::to_static).collect(), *span)
+            }
+        }
+    }
+
+    /// Source range covered by the whole expression
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Binary(left, _, right)
+            | Self::Logical(left, _, right)
+            | Self::Ternary(left, _, right) => left.span().to(right.span()),
+            Self::Unary(operator, right) => operator.span.to(right.span()),
+            Self::Grouping(_, span) | Self::Interpolation(_, span) => *span,
+            Self::Literal(token) | Self::Variable(token, _) | Self::This(token, _) => token.span,
+            Self::Assign(name, value, _) => name.span.to(value.span()),
+            Self::Call(callee, paren, _) => callee.span().to(paren.span),
+            Self::Get(object, name) => object.span().to(name.span),
+            Self::Set(object, _, value) => object.span().to(value.span()),
+            Self::Super(keyword, method, _) => keyword.span.to(method.span),
+        }
+    }
 }
 
 pub trait ReversePolish {
@@ -22,22 +96,45 @@ pub trait ReversePolish {
 impl<'a> Display for Expression<'a> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::Literal(_) => {}
-            Self::Grouping(_) => {}
+            Self::Literal(_) | Self::Variable(..) | Self::This(..) => {}
+            Self::Grouping(..) => {}
             _ => write!(f, "(")?,
         };
 
         match self {
-            Self::Binary(left, token, right) => write!(f, "{} {} {}", left, token, right)?,
-            Self::Unary(token, right) => write!(f, "{} {}", token, right)?,
-            Self::Grouping(exp) => write!(f, "({})", exp)?,
-            Self::Literal(token) => write!(f, "{}", token)?,
+            Self::Binary(left, token, right) => {
+                write!(f, "{} {} {}", left, token.token_type, right)?
+            }
+            Self::Logical(left, token, right) => write!(f, "{} {} {}", left, token.lexeme, right)?,
+            Self::Unary(token, right) => write!(f, "{} {}", token.token_type, right)?,
+            Self::Grouping(exp, _) => write!(f, "({})", exp)?,
+            Self::Literal(token) => write!(f, "{}", token.token_type)?,
+            Self::Variable(name, _) => write!(f, "{}", name.lexeme)?,
+            Self::Assign(name, value, _) => write!(f, "{} = {}", name.lexeme, value)?,
             Self::Ternary(exp1, exp2, exp3) => write!(f, "{} ? {} : {}", exp1, exp2, exp3)?,
+            Self::Call(callee, _, arguments) => {
+                write!(f, "call {}", callee)?;
+                for argument in arguments {
+                    write!(f, " {}", argument)?;
+                }
+            }
+            Self::Get(object, name) => write!(f, "get {} {}", object, name.lexeme)?,
+            Self::Set(object, name, value) => {
+                write!(f, "set {} {} {}", object, name.lexeme, value)?
+            }
+            Self::This(..) => write!(f, "this")?,
+            Self::Super(_, method, _) => write!(f, "super {}", method.lexeme)?,
+            Self::Interpolation(parts, _) => {
+                write!(f, "interpolate")?;
+                for part in parts {
+                    write!(f, " {}", part)?;
+                }
+            }
         };
 
         match self {
-            Self::Literal(_) => {}
-            Self::Grouping(_) => {}
+            Self::Literal(_) | Self::Variable(..) | Self::This(..) => {}
+            Self::Grouping(..) => {}
             _ => write!(f, ")")?,
         };
         Ok(())
@@ -48,17 +145,57 @@ impl<'a> ReversePolish for Expression<'a> {
     fn print_rpn(&self) -> String {
         match self {
             Self::Binary(left, token, right) => {
-                format!("{} {} {}", left.print_rpn(), right.print_rpn(), token)
+                format!(
+                    "{} {} {}",
+                    left.print_rpn(),
+                    right.print_rpn(),
+                    token.token_type
+                )
+            }
+            Self::Logical(left, token, right) => {
+                format!(
+                    "{} {} {}",
+                    left.print_rpn(),
+                    right.print_rpn(),
+                    token.lexeme
+                )
             }
-            Self::Unary(token, right) => format!("{} {}", right.print_rpn(), token),
-            Self::Grouping(exp) => format!("( {} )", exp.print_rpn()),
-            Self::Literal(token) => format!("{}", token),
+            Self::Unary(token, right) => format!("{} {}", right.print_rpn(), token.token_type),
+            Self::Grouping(exp, _) => format!("( {} )", exp.print_rpn()),
+            Self::Literal(token) => format!("{}", token.token_type),
+            Self::Variable(name, _) => name.lexeme.to_string(),
+            Self::Assign(name, value, _) => format!("{} {} =", name.lexeme, value.print_rpn()),
             Self::Ternary(e1, e2, e3) => format!(
                 "{} {} {} : ?",
                 e1.print_rpn(),
                 e2.print_rpn(),
                 e3.print_rpn(),
             ),
+            Self::Call(callee, _, arguments) => {
+                let mut rpn = String::new();
+                for argument in arguments {
+                    rpn.push_str(&argument.print_rpn());
+                    rpn.push(' ');
+                }
+                format!("{}{} call", rpn, callee.print_rpn())
+            }
+            Self::Get(object, name) => format!("{} {} .", object.print_rpn(), name.lexeme),
+            Self::Set(object, name, value) => format!(
+                "{} {} {} .=",
+                object.print_rpn(),
+                name.lexeme,
+                value.print_rpn()
+            ),
+            Self::This(..) => "this".to_string(),
+            Self::Super(_, method, _) => format!("super {} .", method.lexeme),
+            Self::Interpolation(parts, _) => {
+                let mut rpn = String::new();
+                for part in parts {
+                    rpn.push_str(&part.print_rpn());
+                    rpn.push(' ');
+                }
+                format!("{}interpolate", rpn)
+            }
         }
     }
 }
@@ -0,0 +1,793 @@
+use std::collections::HashMap;
+
+use rowan::{NodeOrToken, TextSize};
+use thiserror::Error;
+
+use crate::{
+    cst::{self, SyntaxElement, SyntaxKind, SyntaxNode, SyntaxToken},
+    diagnostics::{Diagnostic, ToDiagnostic},
+    parser::Parser,
+    scanner::Scanner,
+};
+
+/// How [`format`] lays code out
+#[derive(Debug, Clone, Copy)]
+pub struct FormatConfig {
+    /// Lines are wrapped to stay within this many columns where the code
+    /// allows it. Long strings and comments are never split.
+    pub width: usize,
+    /// Spaces per level of indentation
+    pub indent: usize,
+}
+
+impl Default for FormatConfig {
+    fn default() -> Self {
+        Self {
+            width: 80,
+            indent: 4,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum FormatError {
+    #[error("Formatting would change the meaning of the program")]
+    ChangedProgram,
+}
+
+/// Re-emits `source` in the canonical layout, keeping its comments. Code
+/// with lexical or syntax errors is refused with their diagnostics. The
+/// result is parsed again and refused as well if its statements differ, so
+/// formatting never changes what a program does.
+pub fn format(source: &str, config: &FormatConfig) -> Result<String, Vec<Diagnostic>> {
+    let statements = statements(source)?;
+
+    let tree = cst::parse(source);
+    let doc = Formatter::new(&tree).program(&tree);
+    let formatted = Printer::new(config).print(&doc);
+
+    match self::statements(&formatted) {
+        Ok(reformatted) if reformatted == statements => Ok(formatted),
+        _ => Err(vec![FormatError::ChangedProgram.to_diagnostic()]),
+    }
+}
+
+/// The statements of `source` as they display, which leaves out spans and
+/// trivia but nothing that changes what the program does
+fn statements(source: &str) -> Result<Vec<String>, Vec<Diagnostic>> {
+    let mut scanner = Scanner::new(source);
+    let mut diagnostics = match scanner.scan_tokens() {
+        Ok(()) => vec![],
+        Err(errors) => errors.iter().map(ToDiagnostic::to_diagnostic).collect(),
+    };
+
+    match Parser::new(scanner.tokens).parse() {
+        Ok(statements) if diagnostics.is_empty() => {
+            Ok(statements.iter().map(ToString::to_string).collect())
+        }
+        Ok(_) => Err(diagnostics),
+        Err(errors) => {
+            diagnostics.extend(errors.iter().map(ToDiagnostic::to_diagnostic));
+            Err(diagnostics)
+        }
+    }
+}
+
+/// Layout of formatted code in the style of Wadler's "prettier printer":
+/// text and line breaks, where the breaks of a group are all taken or all
+/// left out depending on whether the group fits in the line
+#[derive(Debug)]
+enum Doc {
+    Text(String),
+    /// A space, or a line break when its group doesn't fit
+    Line,
+    /// Nothing, or a line break when its group doesn't fit
+    SoftLine,
+    /// Always a line break, which breaks the groups around it as well
+    HardLine,
+    /// A line break unless nothing was written on the line yet, so comments
+    /// that had a line of their own keep it
+    LineStart,
+    /// Text held back until the end of the line, for `//` comments after
+    /// code. Like a hard line it breaks the groups around it, and the group
+    /// right after it, so no code ends up behind the comment.
+    LineSuffix(String),
+    Concat(Vec<Doc>),
+    Indent(Box<Doc>),
+    Group(Box<Doc>),
+}
+
+fn text(text: &str) -> Doc {
+    Doc::Text(text.to_string())
+}
+
+fn indent(doc: Doc) -> Doc {
+    Doc::Indent(Box::new(doc))
+}
+
+fn group(doc: Doc) -> Doc {
+    Doc::Group(Box::new(doc))
+}
+
+/// A comment and what surrounded it in the source
+#[derive(Debug)]
+struct Comment {
+    text: String,
+    /// A blank line separated the comment from the code or comment before it
+    blank_before: bool,
+    /// The comment was the last thing on its line
+    newline_after: bool,
+}
+
+impl Comment {
+    /// Whether the comment runs to the end of the line, doc comments included
+    fn is_line(&self) -> bool {
+        self.text.starts_with("//")
+    }
+}
+
+/// Comments around a significant token
+#[derive(Debug, Default)]
+struct Attached {
+    /// Comments between the previous line of code and the token
+    leading: Vec<Comment>,
+    /// Comments after the token on the same line
+    trailing: Vec<Comment>,
+    /// A blank line separated the token from the comment or code before it
+    blank_before: bool,
+}
+
+/// Builds the [`Doc`] of a concrete syntax tree. Whitespace is thrown away
+/// and comments are attached to significant tokens up front, so each is
+/// written out exactly once next to the token it belongs to.
+struct Formatter {
+    /// Comments keyed by the offset of their token
+    comments: HashMap<TextSize, Attached>,
+    /// Comments after the last token of the program
+    dangling: Vec<Comment>,
+}
+
+impl Formatter {
+    fn new(tree: &SyntaxNode) -> Self {
+        let mut comments = HashMap::<TextSize, Attached>::new();
+        let mut pending: Vec<Comment> = vec![];
+        let mut previous = None;
+        let mut newlines = 0;
+
+        for token in tree
+            .descendants_with_tokens()
+            .filter_map(|e| e.into_token())
+        {
+            match token.kind() {
+                SyntaxKind::Whitespace => {}
+                SyntaxKind::NewLine => {
+                    if newlines == 0 {
+                        if let Some(comment) = pending.last_mut() {
+                            comment.newline_after = true;
+                        }
+                    }
+                    newlines += 1;
+                }
+                SyntaxKind::Comment | SyntaxKind::DocComment => {
+                    let comment = Comment {
+                        text: token.text().trim_end().to_string(),
+                        blank_before: newlines > 1,
+                        newline_after: false,
+                    };
+                    match previous {
+                        Some(offset) if newlines == 0 && pending.is_empty() => {
+                            comments.entry(offset).or_default().trailing.push(comment)
+                        }
+                        _ => pending.push(comment),
+                    }
+                    newlines = 0;
+                }
+                _ => {
+                    let offset = token.text_range().start();
+                    let attached = comments.entry(offset).or_default();
+                    attached.leading = std::mem::take(&mut pending);
+                    attached.blank_before = newlines > 1;
+                    previous = Some(offset);
+                    newlines = 0;
+                }
+            }
+        }
+
+        Formatter {
+            comments,
+            dangling: pending,
+        }
+    }
+
+    fn program(&mut self, node: &SyntaxNode) -> Doc {
+        let statements = node.children().collect::<Vec<_>>();
+        let dangling = std::mem::take(&mut self.dangling);
+        Doc::Concat(vec![
+            self.statements(&statements),
+            self.dangling(dangling, !statements.is_empty()),
+        ])
+    }
+
+    fn element(&mut self, element: &SyntaxElement) -> Doc {
+        match element {
+            NodeOrToken::Node(node) => self.node(node),
+            NodeOrToken::Token(token) => self.token(token),
+        }
+    }
+
+    fn node(&mut self, node: &SyntaxNode) -> Doc {
+        match node.kind() {
+            SyntaxKind::VarDecl
+            | SyntaxKind::ExprStmt
+            | SyntaxKind::PrintStmt
+            | SyntaxKind::ReturnStmt
+            | SyntaxKind::Superclass
+            | SyntaxKind::AssignExpr => self.spaced(node),
+            SyntaxKind::FunDecl => {
+                let mut parts = significant(node).into_iter();
+                let mut docs = vec![];
+                if let Some(keyword) = parts.next() {
+                    docs.push(self.element(&keyword));
+                    docs.push(text(" "));
+                }
+                docs.push(self.function(parts));
+                Doc::Concat(docs)
+            }
+            SyntaxKind::Method => self.function(significant(node).into_iter()),
+            SyntaxKind::ClassDecl => self.class(node),
+            SyntaxKind::Block => self.block(node),
+            SyntaxKind::IfStmt | SyntaxKind::WhileStmt => self.conditional(node),
+            SyntaxKind::ForStmt => self.for_statement(node),
+            SyntaxKind::ParamList | SyntaxKind::ArgList => self.list(node),
+            SyntaxKind::BinaryExpr | SyntaxKind::LogicalExpr => self.binary(node),
+            SyntaxKind::TernaryExpr => self.ternary(node),
+            SyntaxKind::UnaryExpr => {
+                let parts = significant(node);
+                let mut docs = vec![];
+                for part in &parts {
+                    // `- -x` would read as a decrement written together
+                    if let NodeOrToken::Node(operand) = part {
+                        if operator_of(node) == Some(SyntaxKind::Minus)
+                            && operand.kind() == SyntaxKind::UnaryExpr
+                            && operator_of(operand) == Some(SyntaxKind::Minus)
+                        {
+                            docs.push(text(" "));
+                        }
+                    }
+                    docs.push(self.element(part));
+                }
+                Doc::Concat(docs)
+            }
+            // only a program with errors has error nodes, keep their tokens apart
+            SyntaxKind::Error => self.spaced(node),
+            _ => {
+                let parts = significant(node);
+                Doc::Concat(parts.iter().map(|part| self.element(part)).collect())
+            }
+        }
+    }
+
+    /// The token with its comments
+    fn token(&mut self, token: &SyntaxToken) -> Doc {
+        let attached = self.take_comments(token);
+        self.attach(token, attached)
+    }
+
+    fn take_comments(&mut self, token: &SyntaxToken) -> Attached {
+        self.comments
+            .remove(&token.text_range().start())
+            .unwrap_or_default()
+    }
+
+    fn attach(&mut self, token: &SyntaxToken, attached: Attached) -> Doc {
+        let mut docs = vec![];
+        let mut after_newline = false;
+        for (i, comment) in attached.leading.iter().enumerate() {
+            if i == 0 {
+                docs.push(Doc::LineStart);
+            } else if comment.blank_before {
+                docs.push(Doc::HardLine);
+            }
+            docs.push(text(&comment.text));
+            after_newline = comment.is_line() || comment.newline_after;
+            docs.push(if after_newline {
+                Doc::HardLine
+            } else {
+                text(" ")
+            });
+        }
+        if after_newline && attached.blank_before {
+            docs.push(Doc::HardLine);
+        }
+
+        docs.push(text(token.text()));
+        for comment in &attached.trailing {
+            let comment_text = format!(" {}", comment.text);
+            docs.push(if comment.is_line() {
+                Doc::LineSuffix(comment_text)
+            } else {
+                Doc::Text(comment_text)
+            });
+        }
+        Doc::Concat(docs)
+    }
+
+    /// Comments with no code after them, at the end of a block or program
+    fn dangling(&mut self, comments: Vec<Comment>, after_code: bool) -> Doc {
+        let mut docs = vec![];
+        let mut after_newline = after_code;
+        for (i, comment) in comments.iter().enumerate() {
+            if after_newline {
+                docs.push(Doc::HardLine);
+                if comment.blank_before {
+                    docs.push(Doc::HardLine);
+                }
+            } else if i > 0 {
+                docs.push(text(" "));
+            }
+            docs.push(text(&comment.text));
+            after_newline = comment.is_line() || comment.newline_after;
+        }
+        Doc::Concat(docs)
+    }
+
+    /// Statements one per line, keeping a single blank line where the
+    /// source had one or more
+    fn statements(&mut self, statements: &[SyntaxNode]) -> Doc {
+        let mut docs = vec![];
+        for (i, statement) in statements.iter().enumerate() {
+            if i > 0 {
+                docs.push(Doc::HardLine);
+                if self.blank_before(statement) {
+                    docs.push(Doc::HardLine);
+                }
+            }
+            docs.push(self.node(statement));
+        }
+        Doc::Concat(docs)
+    }
+
+    fn blank_before(&self, node: &SyntaxNode) -> bool {
+        let first = node
+            .descendants_with_tokens()
+            .filter_map(|element| element.into_token())
+            .find(|token| !token.kind().is_trivia());
+        let attached = first.and_then(|token| self.comments.get(&token.text_range().start()));
+        match attached {
+            Some(attached) => match attached.leading.first() {
+                Some(comment) => comment.blank_before,
+                None => attached.blank_before,
+            },
+            None => false,
+        }
+    }
+
+    /// Significant children separated by spaces, with the `;` kept close
+    fn spaced(&mut self, node: &SyntaxNode) -> Doc {
+        let mut docs = vec![];
+        for (i, part) in significant(node).iter().enumerate() {
+            if i > 0 && part.kind() != SyntaxKind::SemiColon {
+                docs.push(text(" "));
+            }
+            docs.push(self.element(part));
+        }
+        Doc::Concat(docs)
+    }
+
+    /// The name, parameters and body of a function or method
+    fn function(&mut self, parts: impl Iterator<Item = SyntaxElement>) -> Doc {
+        let mut docs = vec![];
+        for part in parts {
+            if part.kind() == SyntaxKind::Block {
+                docs.push(text(" "));
+            }
+            docs.push(self.element(&part));
+        }
+        Doc::Concat(docs)
+    }
+
+    fn class(&mut self, node: &SyntaxNode) -> Doc {
+        let mut docs = vec![];
+        let mut members = vec![];
+        let mut open = None;
+        for part in significant(node) {
+            match part {
+                NodeOrToken::Token(token) if token.kind() == SyntaxKind::LeftBrace => {
+                    open = Some(token)
+                }
+                NodeOrToken::Token(token) if token.kind() == SyntaxKind::RightBrace => {
+                    if let Some(open) = open.take() {
+                        docs.push(text(" "));
+                        docs.push(self.braced(&open, &members, &token));
+                    }
+                }
+                NodeOrToken::Node(member) if open.is_some() => members.push(member),
+                part => {
+                    if !docs.is_empty() {
+                        docs.push(text(" "));
+                    }
+                    docs.push(self.element(&part));
+                }
+            }
+        }
+        Doc::Concat(docs)
+    }
+
+    fn block(&mut self, node: &SyntaxNode) -> Doc {
+        let parts = significant(node);
+        match (parts.first(), parts.last()) {
+            (Some(NodeOrToken::Token(open)), Some(NodeOrToken::Token(close)))
+                if close.kind() == SyntaxKind::RightBrace =>
+            {
+                let statements = node.children().collect::<Vec<_>>();
+                self.braced(open, &statements, close)
+            }
+            _ => self.spaced(node),
+        }
+    }
+
+    /// Braces with `items` indented one per line between them, or `{}`
+    fn braced(&mut self, open: &SyntaxToken, items: &[SyntaxNode], close: &SyntaxToken) -> Doc {
+        let open = self.token(open);
+        let items_doc = self.statements(items);
+        let mut attached = self.take_comments(close);
+        let comments = std::mem::take(&mut attached.leading);
+        let close = self.attach(close, attached);
+
+        if items.is_empty() && comments.is_empty() {
+            return Doc::Concat(vec![open, close]);
+        }
+        let dangling = self.dangling(comments, !items.is_empty());
+        Doc::Concat(vec![
+            open,
+            indent(Doc::Concat(vec![Doc::HardLine, items_doc, dangling])),
+            Doc::HardLine,
+            close,
+        ])
+    }
+
+    /// An `if` or `while` statement
+    fn conditional(&mut self, node: &SyntaxNode) -> Doc {
+        let mut docs = vec![];
+        let mut in_condition = true;
+        let mut after_else = false;
+        let mut previous_body = None;
+        for part in significant(node) {
+            match part {
+                NodeOrToken::Token(token) => match token.kind() {
+                    SyntaxKind::If | SyntaxKind::While => {
+                        docs.push(self.token(&token));
+                        docs.push(text(" "));
+                    }
+                    SyntaxKind::RightParen if in_condition => {
+                        docs.push(self.token(&token));
+                        in_condition = false;
+                    }
+                    SyntaxKind::Else => {
+                        docs.push(match previous_body {
+                            Some(SyntaxKind::Block) => text(" "),
+                            _ => Doc::HardLine,
+                        });
+                        docs.push(self.token(&token));
+                        after_else = true;
+                    }
+                    _ => docs.push(self.token(&token)),
+                },
+                NodeOrToken::Node(child) if in_condition => docs.push(self.node(&child)),
+                NodeOrToken::Node(child) => {
+                    if after_else && child.kind() == SyntaxKind::IfStmt {
+                        docs.push(text(" "));
+                        docs.push(self.node(&child));
+                    } else {
+                        docs.push(self.body(&child));
+                    }
+                    previous_body = Some(child.kind());
+                }
+            }
+        }
+        Doc::Concat(docs)
+    }
+
+    fn for_statement(&mut self, node: &SyntaxNode) -> Doc {
+        let mut docs = vec![];
+        let mut after_paren = false;
+        let mut in_clauses = true;
+        for part in significant(node) {
+            match part {
+                NodeOrToken::Token(token) => {
+                    match token.kind() {
+                        SyntaxKind::For => {
+                            docs.push(self.token(&token));
+                            docs.push(text(" "));
+                        }
+                        SyntaxKind::RightParen => {
+                            docs.push(self.token(&token));
+                            in_clauses = false;
+                        }
+                        _ => docs.push(self.token(&token)),
+                    }
+                    after_paren = token.kind() == SyntaxKind::LeftParen;
+                }
+                NodeOrToken::Node(child) if in_clauses => {
+                    if !after_paren {
+                        docs.push(text(" "));
+                    }
+                    docs.push(self.node(&child));
+                    after_paren = false;
+                }
+                NodeOrToken::Node(child) => docs.push(self.body(&child)),
+            }
+        }
+        Doc::Concat(docs)
+    }
+
+    /// The statement run by `if`, `else`, `while` or `for`: a block stays on
+    /// the line, anything else moves to the next one when it doesn't fit
+    fn body(&mut self, node: &SyntaxNode) -> Doc {
+        if node.kind() == SyntaxKind::Block {
+            return Doc::Concat(vec![text(" "), self.block(node)]);
+        }
+        group(indent(Doc::Concat(vec![Doc::Line, self.node(node)])))
+    }
+
+    /// Parameters or arguments in parentheses, one per line when they don't
+    /// fit on one
+    fn list(&mut self, node: &SyntaxNode) -> Doc {
+        let parts = significant(node);
+        let (Some(open), Some(close)) = (parts.first(), parts.last()) else {
+            return Doc::Concat(vec![]);
+        };
+        if parts.len() == 1 || close.kind() != SyntaxKind::RightParen {
+            return self.spaced(node);
+        }
+
+        let open = self.element(open);
+        let mut items = vec![Doc::SoftLine];
+        for part in &parts[1..parts.len() - 1] {
+            items.push(self.element(part));
+            if part.kind() == SyntaxKind::Comma {
+                items.push(Doc::Line);
+            }
+        }
+        let close = self.element(close);
+
+        if parts.len() == 2 {
+            return Doc::Concat(vec![open, close]);
+        }
+        group(Doc::Concat(vec![
+            open,
+            indent(Doc::Concat(items)),
+            Doc::SoftLine,
+            close,
+        ]))
+    }
+
+    /// A chain of operators of the same precedence, broken after each
+    /// operator when it doesn't fit
+    fn binary(&mut self, node: &SyntaxNode) -> Doc {
+        let mut operands = vec![];
+        self.operands(node, &mut operands);
+        let mut operands = operands.into_iter();
+        let first = operands.next().unwrap_or(Doc::Concat(vec![]));
+        group(Doc::Concat(vec![
+            first,
+            indent(Doc::Concat(operands.collect())),
+        ]))
+    }
+
+    /// Flattens `node` into its first operand followed by each operator with
+    /// the operand after it
+    fn operands(&mut self, node: &SyntaxNode, docs: &mut Vec<Doc>) {
+        let parts = significant(node);
+        let [left, operator, right] = parts.as_slice() else {
+            docs.push(self.spaced(node));
+            return;
+        };
+
+        match left {
+            NodeOrToken::Node(left)
+                if matches!(
+                    left.kind(),
+                    SyntaxKind::BinaryExpr | SyntaxKind::LogicalExpr
+                ) && operator_of(left).map(precedence) == Some(precedence(operator.kind())) =>
+            {
+                self.operands(left, docs)
+            }
+            left => docs.push(self.element(left)),
+        }
+        docs.push(Doc::Concat(vec![
+            text(" "),
+            self.element(operator),
+            Doc::Line,
+            self.element(right),
+        ]));
+    }
+
+    fn ternary(&mut self, node: &SyntaxNode) -> Doc {
+        let mut parts = significant(node).into_iter();
+        let condition = match parts.next() {
+            Some(condition) => self.element(&condition),
+            None => return Doc::Concat(vec![]),
+        };
+        let mut branches = vec![];
+        for part in parts {
+            match part.kind() {
+                SyntaxKind::Question | SyntaxKind::Colon => {
+                    branches.push(Doc::Line);
+                    branches.push(self.element(&part));
+                    branches.push(text(" "));
+                }
+                _ => branches.push(self.element(&part)),
+            }
+        }
+        group(Doc::Concat(vec![condition, indent(Doc::Concat(branches))]))
+    }
+}
+
+/// Children of `node` that aren't whitespace or comments
+fn significant(node: &SyntaxNode) -> Vec<SyntaxElement> {
+    node.children_with_tokens()
+        .filter(|element| !element.kind().is_trivia())
+        .collect()
+}
+
+/// The operator of a unary, binary or logical expression
+fn operator_of(node: &SyntaxNode) -> Option<SyntaxKind> {
+    significant(node)
+        .into_iter()
+        .find_map(|part| part.into_token())
+        .map(|operator| operator.kind())
+}
+
+/// Binding strength of a binary or logical operator
+fn precedence(operator: SyntaxKind) -> u8 {
+    match operator {
+        SyntaxKind::Or => 0,
+        SyntaxKind::And => 1,
+        SyntaxKind::EqualEqual | SyntaxKind::NotEqual => 2,
+        SyntaxKind::Greater
+        | SyntaxKind::GreaterEqual
+        | SyntaxKind::Less
+        | SyntaxKind::LessEqual => 3,
+        SyntaxKind::Plus | SyntaxKind::Minus => 4,
+        _ => 5,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    Flat,
+    Break,
+}
+
+/// Writes a [`Doc`] out, deciding for each group whether it fits
+struct Printer<'c> {
+    config: &'c FormatConfig,
+    output: String,
+    column: usize,
+    /// Nothing but indentation was written on the current line
+    line_empty: bool,
+    line_suffix: Vec<String>,
+}
+
+impl<'c> Printer<'c> {
+    fn new(config: &'c FormatConfig) -> Self {
+        Printer {
+            config,
+            output: String::new(),
+            column: 0,
+            line_empty: true,
+            line_suffix: vec![],
+        }
+    }
+
+    fn print(mut self, doc: &Doc) -> String {
+        let mut stack = vec![(0, Mode::Break, doc)];
+        while let Some((indentation, mode, doc)) = stack.pop() {
+            match doc {
+                Doc::Text(text) => self.text(text),
+                Doc::Line if mode == Mode::Flat => self.text(" "),
+                Doc::SoftLine if mode == Mode::Flat => {}
+                Doc::Line | Doc::SoftLine | Doc::HardLine => self.newline(indentation),
+                Doc::LineStart => {
+                    if !self.line_empty {
+                        self.newline(indentation);
+                    }
+                }
+                Doc::LineSuffix(text) => self.line_suffix.push(text.clone()),
+                Doc::Concat(docs) => {
+                    stack.extend(docs.iter().rev().map(|doc| (indentation, mode, doc)))
+                }
+                Doc::Indent(doc) => stack.push((indentation + self.config.indent, mode, doc)),
+                Doc::Group(doc) => {
+                    let mode = if mode == Mode::Flat || self.fits(doc, &stack) {
+                        Mode::Flat
+                    } else {
+                        Mode::Break
+                    };
+                    stack.push((indentation, mode, doc));
+                }
+            }
+        }
+
+        self.flush_line_suffix();
+        let length = self.output.trim_end().len();
+        self.output.truncate(length);
+        if !self.output.is_empty() {
+            self.output.push('\n');
+        }
+        self.output
+    }
+
+    /// Whether `doc` fits flat on the current line, along with whatever
+    /// follows it up to the next line break
+    fn fits(&self, doc: &Doc, rest: &[(usize, Mode, &Doc)]) -> bool {
+        if !self.line_suffix.is_empty() {
+            return false;
+        }
+        let mut remaining = self.config.width as isize - self.column as isize;
+        let mut stack = vec![(Mode::Flat, doc)];
+        let mut rest = rest.iter().rev();
+
+        loop {
+            if remaining < 0 {
+                return false;
+            }
+            let (mode, doc) = match stack.pop() {
+                Some(next) => next,
+                None => match rest.next() {
+                    Some(&(_, mode, doc)) => (mode, doc),
+                    None => return true,
+                },
+            };
+
+            match doc {
+                Doc::Text(text) => match text.split_once('\n') {
+                    Some((line, _)) => return remaining >= width(line),
+                    None => remaining -= width(text),
+                },
+                Doc::Line if mode == Mode::Flat => remaining -= 1,
+                Doc::SoftLine if mode == Mode::Flat => {}
+                Doc::Line | Doc::SoftLine => return true,
+                Doc::HardLine | Doc::LineStart => return mode == Mode::Break,
+                Doc::LineSuffix(_) if mode == Mode::Flat => return false,
+                Doc::LineSuffix(_) => {}
+                Doc::Concat(docs) => stack.extend(docs.iter().rev().map(|doc| (mode, doc))),
+                Doc::Indent(doc) | Doc::Group(doc) => stack.push((mode, doc)),
+            }
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        self.output.push_str(text);
+        match text.rsplit_once('\n') {
+            Some((_, last)) => self.column = width(last) as usize,
+            None => self.column += width(text) as usize,
+        }
+        self.line_empty = false;
+    }
+
+    fn newline(&mut self, indentation: usize) {
+        self.flush_line_suffix();
+        let length = self.output.trim_end_matches(' ').len();
+        self.output.truncate(length);
+        self.output.push('\n');
+        self.output.push_str(&" ".repeat(indentation));
+        self.column = indentation;
+        self.line_empty = true;
+    }
+
+    fn flush_line_suffix(&mut self) {
+        for text in std::mem::take(&mut self.line_suffix) {
+            self.output.push_str(&text);
+        }
+    }
+}
+
+/// Columns taken by `text`, counting each character as one
+fn width(text: &str) -> isize {
+    text.chars().count() as isize
+}
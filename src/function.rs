@@ -0,0 +1,108 @@
+use std::{cell::RefCell, fmt::Debug, rc::Rc};
+
+use crate::{
+    class::Instance,
+    environment::Environment,
+    interpreter::{Interpreter, RuntimeError, Unwind},
+    native::{Native, NativeValue},
+    statement::FunctionDecl,
+    value::Value,
+};
+
+/// Anything that can be invoked with `callee(arguments)`
+pub trait Callable<'a> {
+    fn arity(&self) -> usize;
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter<'a>,
+        arguments: Vec<Value<'a>>,
+    ) -> Result<Value<'a>, RuntimeError>;
+}
+
+/// A user defined function together with the scope it was declared in
+pub struct Function<'a> {
+    pub declaration: Rc<FunctionDecl<'a>>,
+    closure: Rc<RefCell<Environment<'a>>>,
+    is_initializer: bool,
+}
+
+impl<'a> Function<'a> {
+    pub fn new(
+        declaration: Rc<FunctionDecl<'a>>,
+        closure: Rc<RefCell<Environment<'a>>>,
+        is_initializer: bool,
+    ) -> Self {
+        Function {
+            declaration,
+            closure,
+            is_initializer,
+        }
+    }
+
+    /// Creates a copy of this method with `this` bound to `instance`
+    pub fn bind(&self, instance: Rc<RefCell<Instance<'a>>>) -> Function<'a> {
+        let mut scope = Environment::with_enclosing(Rc::clone(&self.closure));
+        scope.define("this", Value::Instance(instance));
+
+        Function::new(
+            Rc::clone(&self.declaration),
+            Rc::new(RefCell::new(scope)),
+            self.is_initializer,
+        )
+    }
+
+    fn this(&self) -> Value<'a> {
+        self.closure
+            .borrow()
+            .get_at(0, "this")
+            .unwrap_or(Value::Nil)
+    }
+}
+
+// the closure may contain the function itself, so it is left out
+impl<'a> Debug for Function<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Function({})", self.declaration.name.lexeme)
+    }
+}
+
+impl<'a> Callable<'a> for Function<'a> {
+    fn arity(&self) -> usize {
+        self.declaration.params.len()
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter<'a>,
+        arguments: Vec<Value<'a>>,
+    ) -> Result<Value<'a>, RuntimeError> {
+        let mut scope = Environment::with_enclosing(Rc::clone(&self.closure));
+        for (param, argument) in self.declaration.params.iter().zip(arguments) {
+            scope.define(&param.lexeme, argument);
+        }
+
+        match interpreter.execute_block(&self.declaration.body, Rc::new(RefCell::new(scope))) {
+            // initializers always hand back the instance they set up
+            Ok(()) | Err(Unwind::Return(_)) if self.is_initializer => Ok(self.this()),
+            Ok(()) => Ok(Value::Nil),
+            Err(Unwind::Return(value)) => Ok(value),
+            Err(Unwind::Error(err)) => Err(err),
+        }
+    }
+}
+
+impl<'a> Callable<'a> for Native {
+    fn arity(&self) -> usize {
+        self.arity
+    }
+
+    fn call(
+        &self,
+        _interpreter: &mut Interpreter<'a>,
+        arguments: Vec<Value<'a>>,
+    ) -> Result<Value<'a>, RuntimeError> {
+        let arguments = arguments.iter().map(NativeValue::from).collect::<Vec<_>>();
+        Ok(Native::call(self, &arguments).into())
+    }
+}
@@ -0,0 +1,539 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    io::{self, Write},
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use thiserror::Error;
+
+use crate::{
+    class::{Class, Instance},
+    environment::Environment,
+    expression::{Depth, Expression},
+    function::{Callable, Function},
+    native::Native,
+    number::{ArithmeticError, Number},
+    statement::{ClassDecl, Stmt},
+    token::{Span, Token, TokenType},
+    value::Value,
+};
+
+#[derive(Debug, Clone, Error, PartialEq)]
+pub enum RuntimeError {
+    #[error("Operand of '{operator}' must be a number")]
+    OperandMustBeNumber { operator: String, span: Span },
+    #[error("Operands of '{operator}' must be numbers")]
+    OperandsMustBeNumbers { operator: String, span: Span },
+    #[error("Operands of '+' must be two numbers or two strings")]
+    InvalidAddition { span: Span },
+    #[error("Undefined variable '{name}'")]
+    UndefinedVariable { name: String, span: Span },
+    #[error("Can only call functions and classes")]
+    NotCallable { span: Span },
+    #[error("Expected {expected} arguments but got {got}")]
+    ArityMismatch {
+        expected: usize,
+        got: usize,
+        span: Span,
+    },
+    #[error("Undefined property '{name}'")]
+    UndefinedProperty { name: String, span: Span },
+    #[error("Only instances have properties")]
+    NotAnInstance { span: Span },
+    #[error("Superclass must be a class")]
+    SuperclassNotAClass { span: Span },
+    #[error("Can't use 'this' outside of a class")]
+    ThisOutsideClass { span: Span },
+    #[error("Can't use 'super' outside of a subclass")]
+    SuperOutsideSubclass { span: Span },
+    #[error("Integer overflow in '{operator}'")]
+    IntegerOverflow { operator: String, span: Span },
+    #[error("Division by zero")]
+    DivisionByZero { span: Span },
+    #[error("Stack overflow")]
+    StackOverflow { span: Span },
+    #[error("Interrupted")]
+    Interrupted { span: Span },
+    #[error("Failed to write output: {0}")]
+    Output(String),
+}
+
+/// Reasons for abandoning the statement being executed
+#[derive(Debug)]
+pub(crate) enum Unwind<'a> {
+    Error(RuntimeError),
+    Return(Value<'a>),
+}
+
+impl<'a> From<RuntimeError> for Unwind<'a> {
+    fn from(err: RuntimeError) -> Self {
+        Unwind::Error(err)
+    }
+}
+
+impl RuntimeError {
+    fn operand_must_be_number(operator: &Token) -> Self {
+        RuntimeError::OperandMustBeNumber {
+            operator: operator.lexeme.to_string(),
+            span: operator.span,
+        }
+    }
+
+    pub(crate) fn undefined_variable(name: &Token) -> Self {
+        RuntimeError::UndefinedVariable {
+            name: name.lexeme.to_string(),
+            span: name.span,
+        }
+    }
+
+    /// Fails with [`RuntimeError::Interrupted`] once `interrupt` is raised,
+    /// lowering it again
+    pub(crate) fn check_interrupt(interrupt: &AtomicBool, span: Span) -> Result<(), Self> {
+        if interrupt.swap(false, Ordering::Relaxed) {
+            return Err(RuntimeError::Interrupted { span });
+        }
+        Ok(())
+    }
+
+    pub(crate) fn arithmetic(err: ArithmeticError, operator: &str, span: Span) -> Self {
+        match err {
+            ArithmeticError::Overflow => RuntimeError::IntegerOverflow {
+                operator: operator.to_string(),
+                span,
+            },
+            ArithmeticError::DivisionByZero => RuntimeError::DivisionByZero { span },
+        }
+    }
+
+    fn operands_must_be_numbers(operator: &Token) -> Self {
+        RuntimeError::OperandsMustBeNumbers {
+            operator: operator.lexeme.to_string(),
+            span: operator.span,
+        }
+    }
+}
+
+/// Calls nested deeper than this are reported as a stack overflow, before
+/// the native stack the tree-walker recurses on runs out
+const CALL_DEPTH_MAX: usize = 1024;
+/// Stack space a call needs left, or else the stack is first grown by
+/// [`STACK_GROWTH`]
+const STACK_RED_ZONE: usize = 128 * 1024;
+const STACK_GROWTH: usize = 4 * 1024 * 1024;
+
+/// Tree-walking evaluator for kai programs
+///
+/// Values are reference counted and never collected, so reference cycles,
+/// such as an instance whose method closure captures it, are never freed.
+/// Programs that build many of them should run on the [`Vm`](crate::vm::Vm).
+pub struct Interpreter<'a> {
+    globals: Rc<RefCell<Environment<'a>>>,
+    environment: Rc<RefCell<Environment<'a>>>,
+    output: Box<dyn Write>,
+    interrupt: Arc<AtomicBool>,
+    /// Calls in progress
+    depth: usize,
+}
+
+impl<'a> Default for Interpreter<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> Interpreter<'a> {
+    pub fn new() -> Self {
+        Self::with_output(Box::new(io::stdout()))
+    }
+
+    /// Creates an interpreter that writes `print` output to `output`
+    pub fn with_output(output: Box<dyn Write>) -> Self {
+        let globals = Rc::new(RefCell::new(Environment::new()));
+        for native in Native::all() {
+            globals
+                .borrow_mut()
+                .define(native.name, Value::NativeFunction(native));
+        }
+
+        Interpreter {
+            environment: Rc::clone(&globals),
+            globals,
+            output,
+            interrupt: Arc::default(),
+            depth: 0,
+        }
+    }
+
+    /// Makes the interpreter stop with [`RuntimeError::Interrupted`] at the
+    /// next loop iteration or call after `interrupt` is raised, from a
+    /// signal handler for instance
+    pub fn with_interrupt(mut self, interrupt: Arc<AtomicBool>) -> Self {
+        self.interrupt = interrupt;
+        self
+    }
+
+    /// Names of the global variables, functions and classes
+    pub fn global_names(&self) -> Vec<String> {
+        self.globals.borrow().names()
+    }
+
+    pub fn interpret(&mut self, statements: &[Stmt<'a>]) -> Result<(), RuntimeError> {
+        for statement in statements {
+            match self.execute(statement) {
+                Ok(()) | Err(Unwind::Return(_)) => {}
+                Err(Unwind::Error(err)) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+
+    fn execute(&mut self, statement: &Stmt<'a>) -> Result<(), Unwind<'a>> {
+        match statement {
+            Stmt::Expression(exp) => {
+                self.evaluate(exp)?;
+            }
+            Stmt::Print(_, exp) => {
+                let value = self.evaluate(exp)?;
+                writeln!(self.output, "{}", value)
+                    .map_err(|err| RuntimeError::Output(err.to_string()))?;
+            }
+            Stmt::Var(name, initializer, _) => {
+                let value = match initializer {
+                    Some(exp) => self.evaluate(exp)?,
+                    None => Value::Nil,
+                };
+                self.environment.borrow_mut().define(&name.lexeme, value);
+            }
+            Stmt::Block(statements, _) => {
+                let scope = Environment::with_enclosing(Rc::clone(&self.environment));
+                self.execute_block(statements, Rc::new(RefCell::new(scope)))?;
+            }
+            Stmt::If(_, condition, then_branch, else_branch) => {
+                if self.evaluate(condition)?.is_truthy() {
+                    self.execute(then_branch)?;
+                } else if let Some(else_branch) = else_branch {
+                    self.execute(else_branch)?;
+                }
+            }
+            Stmt::While(keyword, condition, body) => {
+                while self.evaluate(condition)?.is_truthy() {
+                    RuntimeError::check_interrupt(&self.interrupt, keyword.span)?;
+                    self.execute(body)?;
+                }
+            }
+            Stmt::Function(declaration) => {
+                let function =
+                    Function::new(Rc::clone(declaration), Rc::clone(&self.environment), false);
+                self.environment
+                    .borrow_mut()
+                    .define(&declaration.name.lexeme, Value::Function(Rc::new(function)));
+            }
+            Stmt::Class(declaration) => self.class_declaration(declaration)?,
+            Stmt::Return(_, value) => {
+                let value = match value {
+                    Some(exp) => self.evaluate(exp)?,
+                    None => Value::Nil,
+                };
+                return Err(Unwind::Return(value));
+            }
+        }
+        Ok(())
+    }
+
+    fn class_declaration(&mut self, declaration: &ClassDecl<'a>) -> Result<(), RuntimeError> {
+        let superclass = match &declaration.superclass {
+            Some(exp) => match self.evaluate(exp)? {
+                Value::Class(superclass) => Some(superclass),
+                _ => {
+                    return Err(RuntimeError::SuperclassNotAClass {
+                        span: declaration.name.span,
+                    })
+                }
+            },
+            None => None,
+        };
+
+        self.environment
+            .borrow_mut()
+            .define(&declaration.name.lexeme, Value::Nil);
+
+        // methods of a subclass close over a scope that holds `super`
+        let mut closure = Rc::clone(&self.environment);
+        if let Some(superclass) = &superclass {
+            let mut scope = Environment::with_enclosing(closure);
+            scope.define("super", Value::Class(Rc::clone(superclass)));
+            closure = Rc::new(RefCell::new(scope));
+        }
+
+        let methods = declaration
+            .methods
+            .iter()
+            .map(|method| {
+                let is_initializer = method.name.lexeme == "init";
+                let function =
+                    Function::new(Rc::clone(method), Rc::clone(&closure), is_initializer);
+                (method.name.lexeme.to_string(), Rc::new(function))
+            })
+            .collect::<HashMap<_, _>>();
+
+        let class = Class::new(&declaration.name.lexeme, superclass, methods);
+        self.environment
+            .borrow_mut()
+            .assign(&declaration.name, Value::Class(Rc::new(class)))
+    }
+
+    /// Runs `statements` in `scope`, restoring the current scope afterwards
+    pub(crate) fn execute_block(
+        &mut self,
+        statements: &[Stmt<'a>],
+        scope: Rc<RefCell<Environment<'a>>>,
+    ) -> Result<(), Unwind<'a>> {
+        let previous = std::mem::replace(&mut self.environment, scope);
+        let result = statements
+            .iter()
+            .try_for_each(|statement| self.execute(statement));
+        self.environment = previous;
+        result
+    }
+
+    pub fn evaluate(&mut self, expression: &Expression<'a>) -> Result<Value<'a>, RuntimeError> {
+        match expression {
+            Expression::Literal(token) => Ok(Self::literal(&token.token_type)),
+            Expression::Variable(name, depth) => self.look_up_variable(name, depth),
+            Expression::Assign(name, value, depth) => {
+                let value = self.evaluate(value)?;
+                match depth.get() {
+                    Some(depth) => {
+                        self.environment
+                            .borrow_mut()
+                            .assign_at(depth, name, value.clone())?
+                    }
+                    None => self.globals.borrow_mut().assign(name, value.clone())?,
+                }
+                Ok(value)
+            }
+            Expression::Grouping(exp, _) => self.evaluate(exp),
+            Expression::Unary(operator, right) => {
+                let right = self.evaluate(right)?;
+                Self::unary(operator, right)
+            }
+            Expression::Binary(left, operator, right) => {
+                let left = self.evaluate(left)?;
+                let right = self.evaluate(right)?;
+                Self::binary(left, operator, right)
+            }
+            Expression::Logical(left, operator, right) => {
+                let left = self.evaluate(left)?;
+                let short_circuits = match operator.token_type {
+                    TokenType::Or => left.is_truthy(),
+                    _ => !left.is_truthy(),
+                };
+
+                if short_circuits {
+                    Ok(left)
+                } else {
+                    self.evaluate(right)
+                }
+            }
+            Expression::Ternary(condition, then_exp, else_exp) => {
+                if self.evaluate(condition)?.is_truthy() {
+                    self.evaluate(then_exp)
+                } else {
+                    self.evaluate(else_exp)
+                }
+            }
+            Expression::Call(callee, paren, arguments) => {
+                let callee = self.evaluate(callee)?;
+                let arguments = arguments
+                    .iter()
+                    .map(|argument| self.evaluate(argument))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                RuntimeError::check_interrupt(&self.interrupt, paren.span)?;
+                self.call(callee, paren, arguments)
+            }
+            Expression::Get(object, name) => match self.evaluate(object)? {
+                Value::Instance(instance) => Instance::get(&instance, name),
+                _ => Err(RuntimeError::NotAnInstance { span: name.span }),
+            },
+            Expression::Set(object, name, value) => {
+                let instance = match self.evaluate(object)? {
+                    Value::Instance(instance) => instance,
+                    _ => return Err(RuntimeError::NotAnInstance { span: name.span }),
+                };
+
+                let value = self.evaluate(value)?;
+                instance.borrow_mut().set(name, value.clone());
+                Ok(value)
+            }
+            Expression::This(keyword, depth) => self
+                .look_up_variable(keyword, depth)
+                .map_err(|_| RuntimeError::ThisOutsideClass { span: keyword.span }),
+            Expression::Super(keyword, method, depth) => self.super_method(keyword, method, depth),
+            Expression::Interpolation(parts, _) => {
+                let mut string = String::new();
+                for part in parts {
+                    string.push_str(&self.evaluate(part)?.to_string());
+                }
+                Ok(Value::String(string))
+            }
+        }
+    }
+
+    /// Reads a variable from the scope the resolver bound it to
+    fn look_up_variable(&self, name: &Token, depth: &Depth) -> Result<Value<'a>, RuntimeError> {
+        match depth.get() {
+            Some(depth) => self
+                .environment
+                .borrow()
+                .get_at(depth, &name.lexeme)
+                .ok_or_else(|| RuntimeError::undefined_variable(name)),
+            None => self.globals.borrow().get(name),
+        }
+    }
+
+    fn super_method(
+        &self,
+        keyword: &Token,
+        method: &Token,
+        depth: &Depth,
+    ) -> Result<Value<'a>, RuntimeError> {
+        let outside_subclass = RuntimeError::SuperOutsideSubclass { span: keyword.span };
+        let depth = depth
+            .get()
+            .filter(|depth| *depth > 0)
+            .ok_or(outside_subclass.clone())?;
+
+        // `this` lives in the scope just inside the one holding `super`
+        let environment = self.environment.borrow();
+        let (superclass, instance) = match (
+            environment.get_at(depth, "super"),
+            environment.get_at(depth - 1, "this"),
+        ) {
+            (Some(Value::Class(superclass)), Some(Value::Instance(instance))) => {
+                (superclass, instance)
+            }
+            _ => return Err(outside_subclass),
+        };
+
+        match superclass.find_method(&method.lexeme) {
+            Some(function) => Ok(Value::Function(Rc::new(function.bind(instance)))),
+            None => Err(RuntimeError::UndefinedProperty {
+                name: method.lexeme.to_string(),
+                span: method.span,
+            }),
+        }
+    }
+
+    fn call(
+        &mut self,
+        callee: Value<'a>,
+        paren: &Token,
+        arguments: Vec<Value<'a>>,
+    ) -> Result<Value<'a>, RuntimeError> {
+        let callable: &dyn Callable<'a> = match &callee {
+            Value::Function(function) => function.as_ref(),
+            Value::NativeFunction(native) => native,
+            Value::Class(class) => class,
+            _ => return Err(RuntimeError::NotCallable { span: paren.span }),
+        };
+
+        if arguments.len() != callable.arity() {
+            return Err(RuntimeError::ArityMismatch {
+                expected: callable.arity(),
+                got: arguments.len(),
+                span: paren.span,
+            });
+        }
+
+        if self.depth == CALL_DEPTH_MAX {
+            return Err(RuntimeError::StackOverflow { span: paren.span });
+        }
+        self.depth += 1;
+        // each call recurses through several frames of the evaluator, grow
+        // the stack instead of overflowing it before the limit is reached
+        let result = stacker::maybe_grow(STACK_RED_ZONE, STACK_GROWTH, || {
+            callable.call(self, arguments)
+        });
+        self.depth -= 1;
+        result
+    }
+
+    fn literal(token_type: &TokenType) -> Value<'a> {
+        match token_type {
+            TokenType::Number(val) => Value::Number(Number::Float(*val)),
+            TokenType::Integer(val) => Value::Number(Number::Integer(*val)),
+            TokenType::String(val) => Value::String(val.to_string()),
+            TokenType::True => Value::Bool(true),
+            TokenType::False => Value::Bool(false),
+            _ => Value::Nil,
+        }
+    }
+
+    fn unary(operator: &Token, right: Value<'a>) -> Result<Value<'a>, RuntimeError> {
+        match (&operator.token_type, right) {
+            (TokenType::Minus, Value::Number(val)) => val
+                .checked_neg()
+                .map(Value::Number)
+                .map_err(|err| RuntimeError::arithmetic(err, &operator.lexeme, operator.span)),
+            (TokenType::Minus, _) => Err(RuntimeError::operand_must_be_number(operator)),
+            (_, right) => Ok(Value::Bool(!right.is_truthy())),
+        }
+    }
+
+    fn binary(
+        left: Value<'a>,
+        operator: &Token,
+        right: Value<'a>,
+    ) -> Result<Value<'a>, RuntimeError> {
+        match operator.token_type {
+            TokenType::EqualEqual => return Ok(Value::Bool(left == right)),
+            TokenType::NotEqual => return Ok(Value::Bool(left != right)),
+            TokenType::Plus => {
+                return match (left, right) {
+                    (Value::Number(l), Value::Number(r)) => l
+                        .checked_add(r)
+                        .map(Value::Number)
+                        .map_err(|err| RuntimeError::arithmetic(err, "+", operator.span)),
+                    (Value::String(l), Value::String(r)) => Ok(Value::String(l + &r)),
+                    _ => Err(RuntimeError::InvalidAddition {
+                        span: operator.span,
+                    }),
+                }
+            }
+            _ => {}
+        }
+
+        let (l, r) = match (left, right) {
+            (Value::Number(l), Value::Number(r)) => (l, r),
+            _ => return Err(RuntimeError::operands_must_be_numbers(operator)),
+        };
+
+        let arithmetic = match operator.token_type {
+            TokenType::Minus => Some(l.checked_sub(r)),
+            TokenType::Star => Some(l.checked_mul(r)),
+            TokenType::Slash => Some(l.checked_div(r)),
+            _ => None,
+        };
+        if let Some(result) = arithmetic {
+            return result
+                .map(Value::Number)
+                .map_err(|err| RuntimeError::arithmetic(err, &operator.lexeme, operator.span));
+        }
+
+        let value = match operator.token_type {
+            TokenType::Greater => Value::Bool(l > r),
+            TokenType::GreaterEqual => Value::Bool(l >= r),
+            TokenType::Less => Value::Bool(l < r),
+            TokenType::LessEqual => Value::Bool(l <= r),
+            _ => return Err(RuntimeError::operands_must_be_numbers(operator)),
+        };
+
+        Ok(value)
+    }
+}
@@ -1,11 +1,56 @@
-mod expression;
+pub mod args;
+pub mod chunk;
+pub mod class;
+pub mod compiler;
+pub mod cst;
+pub mod diagnostics;
+pub mod disassembler;
+pub mod environment;
+pub mod expression;
+pub mod formatter;
+pub mod function;
+pub mod interpreter;
+pub mod native;
+pub mod number;
+pub mod object;
 pub mod parser;
+pub mod repl;
+pub mod resolver;
 pub mod scanner;
-mod token;
+pub mod statement;
+pub mod token;
+pub mod value;
+pub mod vm;
 
 #[cfg(test)]
 mod tests {
-    use crate::parser::Parser;
+    use std::{
+        borrow::Cow,
+        cell::RefCell,
+        io::Write,
+        rc::Rc,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+    };
+
+    use crate::args::set_script_args;
+    use crate::chunk::{OpCode, Prototype};
+    use crate::compiler::Compiler;
+    use crate::cst::{self, SyntaxKind};
+    use crate::diagnostics::{Diagnostic, Renderer, ToDiagnostic};
+    use crate::disassembler::disassemble;
+    use crate::formatter::{format, FormatConfig};
+    use crate::interpreter::{Interpreter, RuntimeError};
+    use crate::number::Number;
+    use crate::object::GcConfig;
+    use crate::parser::{ParseError, Parser};
+    use crate::repl::{is_complete, Backend, Command, CommandError, Session};
+    use crate::resolver::{ResolveError, ResolveWarning, Resolver};
+    use crate::statement::{doc_text, Stmt};
+    use crate::value::Value;
+    use crate::vm::Vm;
 
     use super::expression::*;
     use super::scanner::*;
@@ -16,33 +61,49 @@ mod tests {
 
         let _ = scanner.scan_tokens();
 
-        let left_paren = Token::new(TokenType::LeftParen, "(", 1);
-        let right_paren = Token::new(TokenType::RightParen, ")", 1);
-        let eof = Token::new(TokenType::Eof, "", 1);
+        let left_paren = Token::new(TokenType::LeftParen, "(", Span::new(0, 1, 1, 1));
+        let right_paren = Token::new(TokenType::RightParen, ")", Span::new(1, 2, 1, 2));
+        let eof = Token::new(TokenType::Eof, "", Span::new(2, 2, 1, 3));
 
         assert_eq!(vec![left_paren, right_paren, eof], scanner.tokens)
     }
 
+    /// Builds a number literal with no meaningful position
+    fn number(value: i64, lexeme: &str) -> Box<Expression<'_>> {
+        let token = Token::new(TokenType::Integer(value), lexeme, Span::default());
+        Box::new(Expression::Literal(token))
+    }
+
+    fn operator<'a>(token_type: TokenType<'a>, lexeme: &'a str) -> Token<'a> {
+        Token::new(token_type, lexeme, Span::default())
+    }
+
     #[test]
     fn it_pretty_prints() {
-        let a = Box::new(Expression::Literal(TokenType::Number(3.0)));
-        let b = Box::new(Expression::Literal(TokenType::Number(6.0)));
-        let exp = Expression::Binary(a, TokenType::Plus, b);
-        let una = Expression::Unary(TokenType::Minus, Box::new(exp));
+        let exp = Expression::Binary(
+            number(3, "3"),
+            operator(TokenType::Plus, "+"),
+            number(6, "6"),
+        );
+        let una = Expression::Unary(operator(TokenType::Minus, "-"), Box::new(exp));
 
         println!("{}", una);
     }
     #[test]
     fn it_prints_rpn() {
-        let a = Box::new(Expression::Literal(TokenType::Number(1.0)));
-        let b = Box::new(Expression::Literal(TokenType::Number(2.0)));
-        let exp1 = Expression::Binary(a, TokenType::Plus, b);
-
-        let a = Box::new(Expression::Literal(TokenType::Number(4.0)));
-        let b = Box::new(Expression::Literal(TokenType::Number(3.0)));
-        let exp2 = Expression::Binary(a, TokenType::Minus, b);
+        let exp1 = Expression::Binary(
+            number(1, "1"),
+            operator(TokenType::Plus, "+"),
+            number(2, "2"),
+        );
+        let exp2 = Expression::Binary(
+            number(4, "4"),
+            operator(TokenType::Minus, "-"),
+            number(3, "3"),
+        );
 
-        let exp = Expression::Binary(Box::new(exp1), TokenType::Star, Box::new(exp2));
+        let star = operator(TokenType::Star, "*");
+        let exp = Expression::Binary(Box::new(exp1), star, Box::new(exp2));
 
         println!("{}", exp.print_rpn());
     }
@@ -52,13 +113,1323 @@ mod tests {
         let mut scanner = Scanner::new("1 ? 2 : 3");
 
         let _ = scanner.scan_tokens();
-        let exp = Parser::new(scanner.tokens).expression();
+        let exp = Parser::new(scanner.tokens).expression().unwrap();
 
-        let one = Box::new(Expression::Literal(TokenType::Number(1.0)));
-        let two = Box::new(Expression::Literal(TokenType::Number(2.0)));
-        let three = Box::new(Expression::Literal(TokenType::Number(3.0)));
+        let at = |mut literal: Box<Expression<'static>>, start| {
+            if let Expression::Literal(token) = literal.as_mut() {
+                token.span = Span::new(start, start + 1, 1, start + 1);
+            }
+            literal
+        };
+        let one = at(number(1, "1"), 0);
+        let two = at(number(2, "2"), 4);
+        let three = at(number(3, "3"), 8);
         let ternary = Expression::Ternary(one, two, three);
 
         assert_eq!(ternary, exp)
     }
+
+    fn evaluate(source: &str) -> Result<Value<'_>, RuntimeError> {
+        let mut scanner = Scanner::new(source);
+        let _ = scanner.scan_tokens();
+        let exp = Parser::new(scanner.tokens).expression().unwrap();
+
+        Interpreter::new().evaluate(&exp)
+    }
+
+    /// Span of `len` bytes starting at the 1-based `line` and `column` of `source`
+    fn span_at(source: &str, line: usize, column: usize, len: usize) -> Span {
+        let line_start = source
+            .split_inclusive('\n')
+            .take(line - 1)
+            .map(str::len)
+            .sum::<usize>();
+        let start = line_start + column - 1;
+        Span::new(start, start + len, line, column)
+    }
+
+    #[test]
+    fn it_evaluates_arithmetic() {
+        let integer = |val| Ok(Value::Number(Number::Integer(val)));
+        assert_eq!(integer(-7), evaluate("-(1 + 2) * 3 + 4 / 2"));
+        assert_eq!(Ok(Value::Bool(true)), evaluate("1 + 3 >= 4 == !false"));
+        assert_eq!(integer(9), evaluate("Nil ? 4 + 5 : 5 + 4"));
+        assert_eq!(integer(3), evaluate("6 / 2"));
+        assert_eq!(Ok(Value::Number(Number::Float(2.5))), evaluate("10 / 4"));
+        assert_eq!(Ok(Value::Number(Number::Float(3.5))), evaluate("7 / 2.0"));
+        assert_eq!(Ok(Value::Bool(true)), evaluate("1 == 1.0"));
+        assert_eq!(
+            integer(9_007_199_254_740_993),
+            evaluate("9_007_199_254_740_992 + 1")
+        );
+        assert_eq!(
+            Err(RuntimeError::IntegerOverflow {
+                operator: "*".to_string(),
+                span: Span::new(22, 23, 1, 23)
+            }),
+            evaluate("0x7FFF_FFFF_FFFF_FFFF * 2")
+        );
+        assert_eq!(
+            Err(RuntimeError::DivisionByZero {
+                span: Span::new(2, 3, 1, 3)
+            }),
+            evaluate("1 / 0")
+        );
+    }
+
+    #[test]
+    fn it_scans_number_literals() {
+        let mut scanner = Scanner::new("1_000_000 0xFF 0b1010 0o17 2.5 1e9 6.02E-23 1_0.0_1");
+        assert_eq!(Ok(()), scanner.scan_tokens());
+        let token_types = scanner
+            .tokens
+            .iter()
+            .map(|token| token.token_type.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                TokenType::Integer(1_000_000),
+                TokenType::Integer(255),
+                TokenType::Integer(10),
+                TokenType::Integer(15),
+                TokenType::Number(2.5),
+                TokenType::Number(1e9),
+                TokenType::Number(6.02e-23),
+                TokenType::Number(10.01),
+                TokenType::Eof,
+            ],
+            token_types
+        );
+
+        for lexeme in ["1234.", "1e", "1__0", "1_", "0x", "0b102", "12ab", "1._5"] {
+            let source = format!("{};", lexeme);
+            assert_eq!(
+                Err(vec![ScannerError::MalformedNumber {
+                    lexeme: lexeme.to_string(),
+                    span: span_at(&source, 1, 1, lexeme.len())
+                }]),
+                Scanner::new(&source).scan_tokens(),
+                "{}",
+                lexeme
+            );
+
+            // the malformed number still parses as an operand
+            let mut scanner = Scanner::new(&source);
+            let _ = scanner.scan_tokens();
+            assert!(Parser::new(scanner.tokens).parse().is_ok(), "{}", lexeme);
+        }
+        let source = "99999999999999999999";
+        assert_eq!(
+            Err(vec![ScannerError::IntegerOutOfRange {
+                lexeme: source.to_string(),
+                span: span_at(source, 1, 1, source.len())
+            }]),
+            Scanner::new(source).scan_tokens()
+        );
+    }
+
+    #[test]
+    fn it_prints_floats_with_a_fractional_part() {
+        let source = "print 3.0; print 1e3; print 7 / 2; print 6 / 2; print \"${2.0 * 2}\";";
+        let expected = Ok("3.0\n1000.0\n3.5\n3\n4.0\n".to_string());
+        assert_eq!(expected, run(source));
+        assert_eq!(expected, run_vm(source));
+    }
+
+    #[test]
+    fn it_reports_runtime_errors_with_line() {
+        let source = "1 +\n -true";
+        assert_eq!(
+            Err(RuntimeError::OperandMustBeNumber {
+                operator: "-".to_string(),
+                span: span_at(source, 2, 2, 1)
+            }),
+            evaluate(source)
+        );
+    }
+
+    /// Shared buffer that collects everything a program prints
+    #[derive(Clone, Default)]
+    struct Output(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Output {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn run(source: &str) -> Result<String, RuntimeError> {
+        let mut scanner = Scanner::new(source);
+        let _ = scanner.scan_tokens();
+        let statements = Parser::new(scanner.tokens)
+            .parse()
+            .expect("program should parse");
+        Resolver::new()
+            .resolve(&statements)
+            .expect("program should resolve");
+
+        let output = Output::default();
+        Interpreter::with_output(Box::new(output.clone())).interpret(&statements)?;
+        let printed = String::from_utf8(output.0.borrow().clone()).unwrap();
+        Ok(printed)
+    }
+
+    #[test]
+    fn it_runs_statements_with_globals() {
+        let source = "var a = 1;\nvar b;\nprint a + 2;\nprint b;\na;";
+        assert_eq!(Ok("3\nnil\n".to_string()), run(source));
+    }
+
+    #[test]
+    fn it_reports_undefined_variables() {
+        let source = "var a = 1;\nprint missing;";
+        assert_eq!(
+            Err(RuntimeError::UndefinedVariable {
+                name: "missing".to_string(),
+                span: span_at(source, 2, 7, 7)
+            }),
+            run(source)
+        );
+    }
+
+    #[test]
+    fn it_scopes_blocks_and_assigns() {
+        let source = "
+            var a = \"outer\";
+            var b;
+            var c;
+            {
+                var a = \"inner\";
+                print a;
+                b = c = 3;
+            }
+            print a;
+            print b + c;
+        ";
+        assert_eq!(Ok("inner\nouter\n6\n".to_string()), run(source));
+    }
+
+    #[test]
+    fn it_rejects_assignment_to_undeclared_variables() {
+        let source = "{\n  var a = 1;\n}\na = 2;";
+        assert_eq!(
+            Err(RuntimeError::UndefinedVariable {
+                name: "a".to_string(),
+                span: span_at(source, 4, 1, 1)
+            }),
+            run(source)
+        );
+    }
+
+    #[test]
+    fn it_runs_control_flow() {
+        let source = "
+            var total = 0;
+            for (var i = 0; i < 5; i = i + 1) {
+                if (i == 2) total = total + 10; else total = total + i;
+            }
+            var n = 3;
+            while (n > 0) n = n - 1;
+            print total;
+            print n;
+            print Nil or \"fallback\";
+            print false and missing;
+        ";
+        assert_eq!(Ok("18\n0\nfallback\nfalse\n".to_string()), run(source));
+    }
+
+    #[test]
+    fn it_calls_functions_and_closures() {
+        let source = "
+            fun fib(n) {
+                if (n < 2) return n;
+                return fib(n - 1) + fib(n - 2);
+            }
+
+            fun makeCounter() {
+                var count = 0;
+                fun increment() {
+                    count = count + 1;
+                    return count;
+                }
+                return increment;
+            }
+
+            var counter = makeCounter();
+            counter();
+            print counter();
+            print fib(10);
+            print fib;
+        ";
+        assert_eq!(Ok("2\n55\n<fn fib>\n".to_string()), run(source));
+    }
+
+    #[test]
+    fn it_checks_call_arity() {
+        let source = "fun add(a, b) { return a + b; }\nadd(1);";
+        assert_eq!(
+            Err(RuntimeError::ArityMismatch {
+                expected: 2,
+                got: 1,
+                span: span_at(source, 2, 6, 1)
+            }),
+            run(source)
+        );
+        let source = "\"not a function\"();";
+        assert_eq!(
+            Err(RuntimeError::NotCallable {
+                span: span_at(source, 1, 18, 1)
+            }),
+            run(source)
+        );
+    }
+
+    #[test]
+    fn it_runs_classes_with_inheritance() {
+        let source = "
+            class Shape {
+                init(name) {
+                    this.name = name;
+                }
+
+                describe() {
+                    return this.name;
+                }
+            }
+
+            class Square < Shape {
+                init(side) {
+                    super.init(\"square\");
+                    this.side = side;
+                }
+
+                area() {
+                    return this.side * this.side;
+                }
+            }
+
+            var square = Square(3);
+            var area = square.area;
+            print square.describe();
+            print area();
+            print square;
+            print square.init(4) == square;
+        ";
+        assert_eq!(
+            Ok("square\n9\nSquare instance\ntrue\n".to_string()),
+            run(source)
+        );
+    }
+
+    #[test]
+    fn it_reports_class_runtime_errors() {
+        let source = "class A {}\nprint A().missing;";
+        assert_eq!(
+            Err(RuntimeError::UndefinedProperty {
+                name: "missing".to_string(),
+                span: span_at(source, 2, 11, 7)
+            }),
+            run(source)
+        );
+        let source = "var a = 1;\nprint a.field;";
+        assert_eq!(
+            Err(RuntimeError::NotAnInstance {
+                span: span_at(source, 2, 9, 5)
+            }),
+            run(source)
+        );
+    }
+
+    fn resolve(source: &str) -> (Result<(), Vec<ResolveError>>, Vec<ResolveWarning>) {
+        let mut scanner = Scanner::new(source);
+        let _ = scanner.scan_tokens();
+        let statements = Parser::new(scanner.tokens)
+            .parse()
+            .expect("program should parse");
+
+        let mut resolver = Resolver::new();
+        let result = resolver.resolve(&statements);
+        (result, resolver.warnings)
+    }
+
+    #[test]
+    fn it_binds_closures_to_their_declaring_scope() {
+        let source = "
+            var a = \"global\";
+            {
+                fun show() {
+                    print a;
+                }
+
+                show();
+                var a = \"block\";
+                show();
+                print a;
+            }
+        ";
+        assert_eq!(Ok("global\nglobal\nblock\n".to_string()), run(source));
+    }
+
+    #[test]
+    fn it_reports_static_errors_and_warnings() {
+        let source = "
+            return 1;
+            print this;
+            { var a = a; var b; var b; }
+            class A { init() { return 1; } method() { return super.method(); } }
+            fun f() { var unused = 1; }
+        ";
+        let (result, warnings) = resolve(source);
+
+        assert_eq!(
+            Err(vec![
+                ResolveError::TopLevelReturn {
+                    span: span_at(source, 2, 13, 6)
+                },
+                ResolveError::ThisOutsideClass {
+                    span: span_at(source, 3, 19, 4)
+                },
+                ResolveError::ReadInOwnInitializer {
+                    name: "a".to_string(),
+                    span: span_at(source, 4, 23, 1)
+                },
+                ResolveError::DuplicateLocal {
+                    name: "b".to_string(),
+                    span: span_at(source, 4, 37, 1)
+                },
+                ResolveError::ReturnFromInitializer {
+                    span: span_at(source, 5, 32, 6)
+                },
+                ResolveError::SuperWithoutSuperclass {
+                    span: span_at(source, 5, 62, 5)
+                },
+            ]),
+            result
+        );
+        assert_eq!(
+            vec![
+                ResolveWarning::UnusedLocal {
+                    name: "b".to_string(),
+                    span: span_at(source, 4, 37, 1)
+                },
+                ResolveWarning::UnusedLocal {
+                    name: "unused".to_string(),
+                    span: span_at(source, 6, 27, 6)
+                }
+            ],
+            warnings
+        );
+    }
+
+    #[test]
+    fn it_collects_parse_errors_across_statements() {
+        let source = "
+            var a = ;
+            print (1 + 2;
+            { var b = 1 }
+            1 + 2 = 3;
+            print \"still parsed\";
+        ";
+        let mut scanner = Scanner::new(source);
+        let _ = scanner.scan_tokens();
+
+        assert_eq!(
+            Err(vec![
+                ParseError::ExpectedExpression {
+                    found: ";".to_string(),
+                    span: span_at(source, 2, 21, 1)
+                },
+                ParseError::UnexpectedToken {
+                    expected: "Expected ')' after expression".to_string(),
+                    found: ";".to_string(),
+                    span: span_at(source, 3, 25, 1)
+                },
+                ParseError::UnexpectedToken {
+                    expected: "Expected ';' after variable declaration".to_string(),
+                    found: "}".to_string(),
+                    span: span_at(source, 4, 25, 1)
+                },
+                ParseError::InvalidAssignmentTarget {
+                    span: span_at(source, 5, 13, 5)
+                },
+            ]),
+            Parser::new(scanner.tokens).parse()
+        );
+    }
+
+    #[test]
+    fn it_collects_lexical_errors_and_keeps_scanning() {
+        let source = "var a = 1 @ 2;\n  print \"ok\" # /* open\n";
+        let mut scanner = Scanner::new(source);
+
+        assert_eq!(
+            Err(vec![
+                ScannerError::InvalidCharacter {
+                    character: '@',
+                    span: span_at(source, 1, 11, 1)
+                },
+                ScannerError::InvalidCharacter {
+                    character: '#',
+                    span: span_at(source, 2, 14, 1)
+                },
+                ScannerError::UnterminatedComment {
+                    span: span_at(source, 2, 16, 2)
+                },
+            ]),
+            scanner.scan_tokens()
+        );
+
+        let token_types = scanner
+            .tokens
+            .iter()
+            .map(|token| token.token_type.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                TokenType::Var,
+                TokenType::Identifier,
+                TokenType::Equal,
+                TokenType::Integer(1),
+                TokenType::Error,
+                TokenType::Integer(2),
+                TokenType::SemiColon,
+                TokenType::NewLine,
+                TokenType::Print,
+                TokenType::String("ok".into()),
+                TokenType::Error,
+                TokenType::Error,
+                TokenType::Eof,
+            ],
+            token_types
+        );
+        let source = "print \"never closed";
+        assert_eq!(
+            Err(vec![ScannerError::UnterminatedString {
+                span: span_at(source, 1, 7, 13)
+            }]),
+            Scanner::new(source).scan_tokens()
+        );
+
+        // what failed to scan is not reported again by the parser
+        for source in ["var b = @;", "print \"abc", "@ print 1;", "print 1 # 2;"] {
+            let mut scanner = Scanner::new(source);
+            assert!(scanner.scan_tokens().is_err());
+            assert!(Parser::new(scanner.tokens).parse().is_ok(), "{}", source);
+        }
+    }
+
+    #[test]
+    fn it_decodes_string_escapes() {
+        let source = r#"print "tab\there \"quoted\" back\\slash \u{1F600}\n";"#;
+        let mut scanner = Scanner::new(source);
+        assert_eq!(Ok(()), scanner.scan_tokens());
+        assert_eq!(
+            TokenType::String("tab\there \"quoted\" back\\slash \u{1F600}\n".into()),
+            scanner.tokens[1].token_type
+        );
+        assert_eq!(&source[6..source.len() - 1], scanner.tokens[1].lexeme);
+
+        let mut scanner = Scanner::new("\"plain\"");
+        assert_eq!(Ok(()), scanner.scan_tokens());
+        assert!(matches!(
+            &scanner.tokens[0].token_type,
+            TokenType::String(Cow::Borrowed("plain"))
+        ));
+
+        let source = r#"print "a\qb\u{110000}c\u{zz}";"#;
+        let mut scanner = Scanner::new(source);
+        assert_eq!(
+            Err(vec![
+                ScannerError::InvalidEscape {
+                    sequence: r"\q".to_string(),
+                    span: span_at(source, 1, 9, 2)
+                },
+                ScannerError::InvalidUnicodeEscape {
+                    sequence: r"\u{110000}".to_string(),
+                    span: span_at(source, 1, 12, 10)
+                },
+                ScannerError::InvalidUnicodeEscape {
+                    sequence: r"\u{".to_string(),
+                    span: span_at(source, 1, 23, 3)
+                },
+            ]),
+            scanner.scan_tokens()
+        );
+        // the string is still produced so parsing can carry on
+        assert_eq!(TokenType::SemiColon, scanner.tokens[2].token_type);
+    }
+
+    #[test]
+    fn it_interpolates_strings() {
+        let mut scanner = Scanner::new(r#""a ${x + "}"} b ${y}""#);
+        assert_eq!(Ok(()), scanner.scan_tokens());
+        let token_types = scanner
+            .tokens
+            .iter()
+            .map(|token| token.token_type.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                TokenType::Interpolation("a ".into()),
+                TokenType::Identifier,
+                TokenType::Plus,
+                TokenType::String("}".into()),
+                TokenType::InterpolationPart(" b ".into()),
+                TokenType::Identifier,
+                TokenType::InterpolationEnd("".into()),
+                TokenType::Eof,
+            ],
+            token_types
+        );
+        assert_eq!("} b ${", scanner.tokens[4].lexeme);
+
+        let source = "
+            var name = \"kai\";
+            print \"hello ${name}, you are ${1 + 2}\";
+            print \"${\"nested ${name + \"!\"}\"} \\${not} $\";
+        ";
+        assert_eq!(
+            Ok("hello kai, you are 3\nnested kai! ${not} $\n".to_string()),
+            run(source)
+        );
+
+        // the piece closing an interpolation is not a string operand
+        for source in [
+            "var x = \"a\"; print \"[${x + }]\" \"y\";",
+            "print \"${1 +}\";",
+        ] {
+            let mut scanner = Scanner::new(source);
+            assert_eq!(Ok(()), scanner.scan_tokens());
+            let errors = Parser::new(scanner.tokens).parse().unwrap_err();
+            assert!(
+                matches!(
+                    errors.as_slice(),
+                    [ParseError::ExpectedExpression { found, .. }] if found.starts_with('}')
+                ),
+                "{:?}",
+                errors
+            );
+        }
+
+        let source = "print \"open ${1 + 2;";
+        assert_eq!(
+            Err(vec![ScannerError::UnterminatedInterpolation {
+                span: span_at(source, 1, 13, 2)
+            }]),
+            Scanner::new(source).scan_tokens()
+        );
+    }
+
+    #[test]
+    fn it_scans_unicode_identifiers() {
+        let source = "var my_var = \"héllo\"; var π_2 = 2; print my_var + \"${π_2}\";";
+        let mut scanner = Scanner::new(source);
+        assert_eq!(Ok(()), scanner.scan_tokens());
+        let identifiers = scanner
+            .tokens
+            .iter()
+            .filter(|token| token.token_type == TokenType::Identifier)
+            .map(|token| token.lexeme.as_ref())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["my_var", "π_2", "my_var", "π_2"], identifiers);
+        assert_eq!("\"héllo\"", scanner.tokens[3].lexeme);
+        assert_eq!(Span::new(27, 31, 1, 27), scanner.tokens[6].span);
+        assert_eq!(Ok("héllo2\n".to_string()), run(source));
+
+        // a precomposed and a decomposed é name the same variable
+        let source = "var caf\u{e9} = 1; print cafe\u{301};";
+        assert_eq!(Ok("1\n".to_string()), run(source));
+        assert_eq!(Ok("1\n".to_string()), run_vm(source));
+        // but strings are data and keep the characters they were written with
+        let source = "print \"e\u{301}\" == \"\u{e9}\";";
+        assert_eq!(Ok("false\n".to_string()), run(source));
+
+        let source = "var ünïcödé = 1 € 2;";
+        assert_eq!(
+            Err(vec![ScannerError::InvalidCharacter {
+                character: '€',
+                span: Span::new(20, 23, 1, 17)
+            }]),
+            Scanner::new(source).scan_tokens()
+        );
+    }
+
+    #[test]
+    fn it_skips_nested_block_comments() {
+        let source = "print 1; /* outer /* inner */ still ** comment */ print 2;";
+        assert_eq!(Ok("1\n2\n".to_string()), run(source));
+
+        let source = "/* outer /* inner */\nprint 1;";
+        assert_eq!(
+            Err(vec![ScannerError::UnterminatedComment {
+                span: span_at(source, 1, 1, 2)
+            }]),
+            Scanner::new(source).scan_tokens()
+        );
+    }
+
+    #[test]
+    fn it_attaches_doc_comments_to_declarations() {
+        let source = "
+            /// A point.
+            ///
+            ///   Indented line
+            class Point {
+                /// Distance to the origin
+                length() { return 0; }
+                plain() {}
+            }
+            //// not a doc comment
+            fun undocumented() {}
+            /// The answer
+            var answer = 42;
+            /// dropped, a statement can't be documented
+            print 1;
+        ";
+        let mut scanner = Scanner::new(source);
+        assert_eq!(Ok(()), scanner.scan_tokens());
+        let statements = Parser::new(scanner.tokens).parse().unwrap();
+
+        let Stmt::Class(class) = &statements[0] else {
+            panic!("expected a class");
+        };
+        assert_eq!("A point.\n\n  Indented line", doc_text(&class.docs));
+        assert_eq!("Distance to the origin", doc_text(&class.methods[0].docs));
+        assert!(class.methods[1].docs.is_empty());
+        let Stmt::Function(function) = &statements[1] else {
+            panic!("expected a function");
+        };
+        assert!(function.docs.is_empty());
+        let Stmt::Var(_, _, docs) = &statements[2] else {
+            panic!("expected a variable declaration");
+        };
+        assert_eq!("The answer", doc_text(docs));
+        assert_eq!(4, statements.len());
+    }
+
+    #[test]
+    fn it_tracks_source_spans() {
+        let source = "var total = 1 +\n  (2 * x);\nprint total;";
+        let mut scanner = Scanner::new(source);
+        assert_eq!(Ok(()), scanner.scan_tokens());
+        let statements = Parser::new(scanner.tokens).parse().unwrap();
+
+        let Stmt::Var(name, Some(initializer), _) = &statements[0] else {
+            panic!("expected a variable declaration");
+        };
+        assert_eq!(Span::new(4, 9, 1, 5), name.span);
+        assert_eq!(Span::new(12, 25, 1, 13), initializer.span());
+        assert_eq!("1 +\n  (2 * x)", &source[12..25]);
+
+        let Expression::Binary(_, operator, right) = initializer else {
+            panic!("expected a binary expression");
+        };
+        assert_eq!(Span::new(14, 15, 1, 15), operator.span);
+        assert_eq!(Span::new(18, 25, 2, 3), right.span());
+
+        assert_eq!(Span::new(4, 25, 1, 5), statements[0].span());
+        assert_eq!(Span::new(27, 38, 3, 1), statements[1].span());
+    }
+
+    #[test]
+    fn it_renders_caret_diagnostics() {
+        let source = "var a = 1;\nprint a + missing;\n";
+        let renderer = Renderer::new("script.kai", source).with_color(false);
+
+        let error = run(source).unwrap_err();
+        assert_eq!(
+            "error[E0304]: Undefined variable 'missing'\n \
+             --> script.kai:2:11\n  \
+             |\n\
+             2 | print a + missing;\n  \
+             |           ^^^^^^^\n",
+            renderer.render(&error.to_diagnostic())
+        );
+
+        let warning = ResolveWarning::UnusedLocal {
+            name: "a".to_string(),
+            span: span_at(source, 1, 5, 1),
+        };
+        assert_eq!(
+            "warning[W0201]: Local variable 'a' is never used\n \
+             --> script.kai:1:5\n  \
+             |\n\
+             1 | var a = 1;\n  \
+             |     ^\n  \
+             = help: remove the variable if it is not needed\n",
+            renderer.render(&warning.to_diagnostic())
+        );
+
+        let unplaced = ResolveWarning::UnusedLocal {
+            name: "a".to_string(),
+            span: Span::default(),
+        };
+        assert_eq!(
+            "warning[W0201]: Local variable 'a' is never used\n \
+             --> script.kai\n \
+             = help: remove the variable if it is not needed\n",
+            renderer.render(&unplaced.to_diagnostic())
+        );
+    }
+
+    fn compile(source: &str) -> Rc<Prototype> {
+        let mut scanner = Scanner::new(source);
+        let _ = scanner.scan_tokens();
+        let statements = Parser::new(scanner.tokens)
+            .parse()
+            .expect("program should parse");
+        Resolver::new()
+            .resolve(&statements)
+            .expect("program should resolve");
+        Compiler::new()
+            .compile(&statements)
+            .expect("program should compile")
+    }
+
+    /// Like `run`, but compiles the program and runs it on the VM
+    fn run_vm(source: &str) -> Result<String, RuntimeError> {
+        run_vm_with(source, GcConfig::default())
+    }
+
+    fn run_vm_with(source: &str, gc: GcConfig) -> Result<String, RuntimeError> {
+        let output = Output::default();
+        Vm::with_output(Box::new(output.clone()))
+            .with_gc(gc)
+            .interpret(compile(source))?;
+        let printed = String::from_utf8(output.0.borrow().clone()).unwrap();
+        Ok(printed)
+    }
+
+    #[test]
+    fn it_decodes_every_opcode() {
+        for (byte, op) in OpCode::ALL.iter().enumerate() {
+            assert_eq!(Some(*op), OpCode::from_byte(byte as u8));
+        }
+        assert_eq!(None, OpCode::from_byte(OpCode::ALL.len() as u8));
+    }
+
+    #[test]
+    fn it_disassembles_nested_functions() {
+        let script = compile("var a = 1;\nfun f(x) {\n  return a + x;\n}\nprint f(2);");
+        let listing = disassemble(&script);
+        let expected = [
+            "== <script> ==",
+            "constants:",
+            "    0  1",
+            "    1  \"a\"",
+            "    2  \"f\"",
+            "    3  <fn f>",
+            "    4  2",
+            "code:",
+            "0000     1 Constant            0  1",
+            "0003     | DefineGlobal        1  \"a\"",
+            "0006     2 Closure             3  <fn f>",
+            "0009     | DefineGlobal        2  \"f\"",
+            "0012     5 GetGlobal           2  \"f\"",
+            "0015     | Constant            4  2",
+            "0018     | Call                1",
+            "0020     | Print",
+            "0021     | Nil",
+            "0022     | Return",
+            "",
+            "== <fn f> ==",
+            "constants:",
+            "    0  \"a\"",
+            "code:",
+            "0000     3 GetGlobal           0  \"a\"",
+            "0003     | GetLocal            1",
+            "0005     | Add",
+            "0006     | Return",
+            "0007     2 Nil",
+            "0008     | Return",
+            "",
+        ];
+        assert_eq!(expected.join("\n"), listing);
+    }
+
+    #[test]
+    fn it_runs_programs_the_same_on_the_vm() {
+        let programs = [
+            "print 1 + 2 * 3; print -(4 / 8) >= 0; print \"a\" + \"b\"; print 1 == 1 != false;",
+            "var a = 1; { var a = 2; { var b = a + 1; print b; } print a; } print a;",
+            "
+            var total = 0;
+            for (var i = 0; i < 5; i = i + 1) {
+                if (i == 2) total = total + 10; else total = total + i;
+            }
+            var n = 3;
+            while (n > 0) n = n - 1;
+            print total; print n; print Nil or \"x\"; print 1 and 2; print false ? 1 : 2;
+            ",
+            "
+            fun makeCounter() {
+                var count = 0;
+                fun increment() { count = count + 1; return count; }
+                return increment;
+            }
+            var first = makeCounter();
+            var second = makeCounter();
+            first(); first();
+            print first(); print second(); print makeCounter; print clock;
+            ",
+            "
+            var getter; var setter;
+            {
+                var shared = \"before\";
+                fun get() { return shared; }
+                fun set(value) { shared = value; }
+                getter = get; setter = set;
+            }
+            setter(\"after\");
+            print getter();
+            for (var i = 0; i < 2; i = i + 1) { fun show() { print i; } show(); }
+            ",
+            "
+            fun fib(n) { if (n < 2) return n; return fib(n - 1) + fib(n - 2); }
+            print fib(15);
+            ",
+            "
+            class Shape {
+                init(name) { this.name = name; }
+                describe() { return this.name; }
+            }
+            class Square < Shape {
+                init(side) { super.init(\"square\"); this.side = side; }
+                area() { return this.side * this.side; }
+                describe() { return \"a \" + super.describe(); }
+            }
+            var square = Square(3);
+            var area = square.area;
+            print square.describe(); print area(); print square; print Square;
+            print square.init(4) == square; print square.side; print area;
+            square.name = \"renamed\";
+            print square.describe();
+            ",
+            "print 7 / 2; print 6 / 2; print 7 / 2.0; print 1 == 1.0; print 0.1 + 0.2; print 1e3 * 2;",
+            "var n = 2; print \"${n} + ${n} = ${n + n}, ${Nil} ${\"in${\"ner\"}\"}\";",
+        ];
+
+        let stress = GcConfig {
+            stress: true,
+            ..GcConfig::default()
+        };
+        for program in programs {
+            assert_eq!(run(program), run_vm(program), "{}", program);
+            assert_eq!(run(program), run_vm_with(program, stress), "{}", program);
+        }
+    }
+
+    #[test]
+    fn it_collects_unreachable_cycles() {
+        let script = compile(
+            "
+            class Node {
+                init() {
+                    this.me = this;
+                    fun get() { return this; }
+                    this.get = get;
+                }
+            }
+            var kept = Node();
+            for (var i = 0; i < 1000; i = i + 1) { Node(); }
+            print kept.get().me == kept;
+            ",
+        );
+
+        let output = Output::default();
+        let mut vm = Vm::with_output(Box::new(output.clone())).with_gc(GcConfig {
+            initial_threshold: 64,
+            ..GcConfig::default()
+        });
+        vm.interpret(script).unwrap();
+        assert_eq!(
+            "true\n",
+            String::from_utf8(output.0.borrow().clone()).unwrap()
+        );
+        assert!(
+            vm.live_objects() < 128,
+            "{} objects alive",
+            vm.live_objects()
+        );
+    }
+
+    #[test]
+    fn it_reports_the_same_runtime_errors_on_the_vm() {
+        let programs = [
+            "1 +\n -true;",
+            "print 1 < \"a\";",
+            "print \"a\" + 1;",
+            "var a = 1;\nprint missing;",
+            "{\n  var a = 1;\n}\na = 2;",
+            "fun add(a, b) { return a + b; }\nadd(1);",
+            "\"not a function\"();",
+            "class A {}\nprint A().missing;",
+            "var a = 1;\nprint a.field;",
+            "var A = 1;\nclass B < A {}",
+            "class A { init(a) {} }\nA();",
+            "print \"before\";\nprint 1 - Nil;",
+            "print 10 / (5 - 5);",
+            "var big = 0x7FFF_FFFF_FFFF_FFFF;\nprint big + 1;",
+            "print -(-0x7FFF_FFFF_FFFF_FFFF - 1);",
+        ];
+
+        for program in programs {
+            let expected = run(program);
+            assert!(expected.is_err(), "{}", program);
+            assert_eq!(expected, run_vm(program), "{}", program);
+        }
+
+        let source = "fun recurse() { recurse(); }\nrecurse();";
+        let overflow = Err(RuntimeError::StackOverflow {
+            span: span_at(source, 1, 25, 1),
+        });
+        assert_eq!(overflow, run(source));
+        assert_eq!(overflow, run_vm(source));
+
+        let source = "class A { init(n) { if (n > 0) A(n - 1); } }\nA(5000);";
+        assert_eq!(run(source), run_vm(source));
+        assert!(matches!(
+            run(source),
+            Err(RuntimeError::StackOverflow { .. })
+        ));
+    }
+
+    #[test]
+    fn it_keeps_trivia_in_lossless_mode() {
+        let source = "var a\t= 1; // one\n/* two */ @";
+        let mut scanner = Scanner::new(source).lossless();
+        assert!(scanner.scan_tokens().is_err());
+
+        let lexemes = scanner
+            .tokens
+            .iter()
+            .map(|token| token.lexeme.as_ref())
+            .collect::<String>();
+        assert_eq!(source, lexemes);
+        let token_types = scanner
+            .tokens
+            .iter()
+            .map(|token| token.token_type.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                TokenType::Var,
+                TokenType::Whitespace,
+                TokenType::Identifier,
+                TokenType::Whitespace,
+                TokenType::Equal,
+                TokenType::Whitespace,
+                TokenType::Integer(1),
+                TokenType::SemiColon,
+                TokenType::Whitespace,
+                TokenType::Comment,
+                TokenType::NewLine,
+                TokenType::Comment,
+                TokenType::Whitespace,
+                TokenType::Error,
+                TokenType::Eof,
+            ],
+            token_types
+        );
+    }
+
+    #[test]
+    fn it_round_trips_source_through_the_cst() {
+        let sources = [
+            "/// Greets\nfun greet(name) {\n  print \"hi ${name}\"; // inline\n}\n",
+            "class A < B { m() { return super.m(this.x); } }\n",
+            "for (var i = 0; i < 3; i = i + 1) /* nested /* */ */ print -i;",
+            "print @ 1 +; } var = ;\n\"never closed",
+        ];
+        for source in sources {
+            assert_eq!(source, cst::parse(source).to_string());
+        }
+
+        let program = cst::parse("var x = a or b;");
+        let nodes = program
+            .descendants()
+            .map(|node| (node.kind(), node.text_range().start().into()))
+            .collect::<Vec<(SyntaxKind, usize)>>();
+        assert_eq!(
+            vec![
+                (SyntaxKind::Program, 0),
+                (SyntaxKind::VarDecl, 0),
+                (SyntaxKind::LogicalExpr, 8),
+                (SyntaxKind::NameRef, 8),
+                (SyntaxKind::NameRef, 13),
+            ],
+            nodes
+        );
+
+        let error = cst::parse("print ) ;");
+        assert!(error
+            .descendants()
+            .any(|node| node.kind() == SyntaxKind::Error));
+
+        // a declaration that fails to parse is an error node up to where the
+        // parser picks up again, which can swallow what looks like a statement
+        for (source, statements) in [
+            (
+                "print 1 2; x;",
+                vec![
+                    (SyntaxKind::Error, "print 1 2;"),
+                    (SyntaxKind::ExprStmt, "x;"),
+                ],
+            ),
+            (
+                "if (x) print 1 else print 2;",
+                vec![
+                    (SyntaxKind::Error, "if (x) print 1 else"),
+                    (SyntaxKind::PrintStmt, "print 2;"),
+                ],
+            ),
+            (
+                "print 1.; { print 1 var x; }",
+                vec![
+                    (SyntaxKind::PrintStmt, "print 1.;"),
+                    (SyntaxKind::Block, "{ print 1 var x; }"),
+                ],
+            ),
+        ] {
+            let program = cst::parse(source);
+            let children = program
+                .children()
+                .map(|node| (node.kind(), node.to_string()))
+                .collect::<Vec<_>>();
+            let expected = statements
+                .into_iter()
+                .map(|(kind, text)| (kind, text.to_string()))
+                .collect::<Vec<_>>();
+            assert_eq!(expected, children, "{}", source);
+        }
+    }
+
+    #[test]
+    fn it_keeps_repl_state_between_inputs() {
+        for backend in [Backend::TreeWalker, Backend::Vm(GcConfig::default())] {
+            let output = Output::default();
+            let mut session = Session::with_output(backend, Box::new(output.clone()));
+            for input in [
+                "var count = 1;",
+                "fun bump(by) {\n  count = count + by;\n  return count;\n}",
+                "bump(2)",
+                "count = 10",
+                "\"count is ${count}\"",
+            ] {
+                assert_eq!(Vec::<Diagnostic>::new(), session.eval(input));
+            }
+            assert_eq!(
+                "3\ncount is 10\n",
+                String::from_utf8(output.0.borrow().clone()).unwrap()
+            );
+
+            // definitions outlive the text they were read from
+            let input = String::from("fun twice(x) { return x * 2; }");
+            assert!(session.eval(&input).is_empty());
+            drop(input);
+            assert!(session.eval("print twice(4);").is_empty());
+
+            let diagnostics = session.eval("print missing;");
+            assert_eq!(
+                vec!["E0304"],
+                diagnostics.iter().map(|d| d.code).collect::<Vec<_>>()
+            );
+            assert!(session.eval("count").is_empty());
+        }
+    }
+
+    #[test]
+    fn it_detects_incomplete_repl_input() {
+        assert!(is_complete("print 1;"));
+        assert!(is_complete(""));
+        assert!(is_complete("}"));
+        assert!(!is_complete("fun f() {\n"));
+        assert!(!is_complete("print (1 +\n"));
+        assert!(!is_complete("print \"open\n"));
+        assert!(!is_complete("/* open\n"));
+        assert!(!is_complete("print \"${1 +\n"));
+    }
+
+    #[test]
+    fn it_completes_reserved_words_and_globals() {
+        let mut session = Session::with_output(Backend::TreeWalker, Box::new(Output::default()));
+        assert!(session.eval("var whale = 1; fun whisper() {}").is_empty());
+
+        assert_eq!(
+            (
+                6,
+                vec![
+                    "whale".to_string(),
+                    "while".to_string(),
+                    "whisper".to_string()
+                ]
+            ),
+            session.completions("print wh", 8)
+        );
+        assert_eq!(
+            (
+                0,
+                vec!["false".to_string(), "for".to_string(), "fun".to_string()]
+            ),
+            session.completions("f", 1)
+        );
+        assert_eq!((3, vec![]), session.completions("1 +", 3));
+    }
+
+    #[test]
+    fn it_interrupts_a_running_program() {
+        for backend in [Backend::TreeWalker, Backend::Vm(GcConfig::default())] {
+            let interrupt = Arc::new(AtomicBool::new(true));
+            let mut session = Session::with_output(backend, Box::new(Output::default()))
+                .with_interrupt(Arc::clone(&interrupt));
+
+            let diagnostics = session.eval("while (true) {}");
+            assert_eq!(
+                vec!["E0316"],
+                diagnostics.iter().map(|d| d.code).collect::<Vec<_>>()
+            );
+            assert!(!interrupt.load(Ordering::Relaxed));
+            assert!(session.eval("print 1;").is_empty());
+        }
+    }
+
+    #[test]
+    fn it_parses_repl_commands() {
+        assert_eq!(
+            Ok(Command::Ast("print a;")),
+            Command::parse(":ast   print a;\n")
+        );
+        assert_eq!(
+            Ok(Command::Load("lib.kai")),
+            Command::parse(":load lib.kai")
+        );
+        assert_eq!(Ok(Command::Reset), Command::parse(":reset"));
+        assert_eq!(
+            Err(CommandError::MissingArgument {
+                name: ":time".to_string()
+            }),
+            Command::parse(":time ")
+        );
+        assert_eq!(
+            Err(CommandError::Unknown {
+                name: ":quit".to_string()
+            }),
+            Command::parse(":quit now")
+        );
+    }
+
+    #[test]
+    fn it_runs_repl_commands() {
+        let output = Output::default();
+        let mut session = Session::with_output(Backend::TreeWalker, Box::new(output.clone()));
+        let printed =
+            |output: &Output| String::from_utf8(output.0.borrow_mut().split_off(0)).unwrap();
+
+        assert!(session.tokens("x;").is_empty());
+        assert_eq!(
+            "1:1     Identifier           \"x\"\n1:2     SemiColon            \";\"\n1:3     Eof                  \"\"\n",
+            printed(&output)
+        );
+        assert!(session.ast("print a; { b = c; }").is_empty());
+        assert_eq!("(print a);\n{ (b = c); }\n", printed(&output));
+        assert!(session.rpn("-a * (b + c)").is_empty());
+        assert_eq!("a - ( b c + ) *\n", printed(&output));
+        assert_eq!(
+            vec!["E0101"],
+            session
+                .rpn("1 + 2 3")
+                .iter()
+                .map(|d| d.code)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!("", printed(&output));
+        assert_eq!(
+            vec!["E0102"],
+            session
+                .ast("print ;")
+                .iter()
+                .map(|d| d.code)
+                .collect::<Vec<_>>()
+        );
+
+        // only an expression left without its `;` is echoed, and a loaded
+        // file is run as it is
+        assert!(session.eval("fun f() {}").is_empty());
+        assert!(session.eval("f();").is_empty());
+        assert_eq!(
+            vec!["E0101"],
+            session
+                .run("2 + 2")
+                .iter()
+                .map(|d| d.code)
+                .collect::<Vec<_>>()
+        );
+        assert!(session.run("1 + 1;\n2 + 2;").is_empty());
+        assert_eq!("", printed(&output));
+        assert!(session.eval("f()").is_empty());
+        assert_eq!("nil\n", printed(&output));
+
+        assert!(session.time("var kept = true;").is_empty());
+        assert!(printed(&output).starts_with("took "));
+        session.reset();
+        assert_eq!(
+            vec!["E0304"],
+            session
+                .eval("kept")
+                .iter()
+                .map(|d| d.code)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn it_exposes_script_arguments() {
+        set_script_args(vec!["first".to_string(), "second".to_string()]);
+        let source = "print argc(); print arg(1); print arg(2); print arg(-1); print arg(0.5);";
+        let expected = Ok("2\nsecond\nnil\nnil\nnil\n".to_string());
+        assert_eq!(expected, run(source));
+        assert_eq!(expected, run_vm(source));
+
+        set_script_args(vec![]);
+        assert_eq!(Ok("0\n".to_string()), run("print argc();"));
+    }
+
+    #[test]
+    fn it_formats_source_keeping_comments() {
+        let source = "// counts\nvar   x=1+2*3 ; // trailing\n/// Adds\nfun add(a,b){return a+b;}\n\n\n\
+                      class P<Base{ init(x){this.x=x;}\n  /* last */ }\nif(x>1)print \"big\";else{print - -x;}\n\
+                      for(var i=0;i<3;i=i+1){}\n";
+        let expected = "// counts\nvar x = 1 + 2 * 3; // trailing\n/// Adds\nfun add(a, b) {\n    return a + b;\n}\n\n\
+                        class P < Base {\n    init(x) {\n        this.x = x;\n    }\n    /* last */\n}\n\
+                        if (x > 1) print \"big\";\nelse {\n    print - -x;\n}\nfor (var i = 0; i < 3; i = i + 1) {}\n";
+        let config = FormatConfig::default();
+        let formatted = format(source, &config).unwrap();
+        assert_eq!(expected, formatted);
+        assert_eq!(Ok(formatted.clone()), format(&formatted, &config));
+    }
+
+    #[test]
+    fn it_wraps_formatted_code_to_the_width() {
+        let config = FormatConfig {
+            width: 20,
+            ..FormatConfig::default()
+        };
+        let source =
+            "print call(first, second, third); // why\nvar total = first + second + third;";
+        let expected = "print call(\n    first,\n    second,\n    third\n); // why\nvar total = first +\n    second +\n    third;\n";
+        let formatted = format(source, &config).unwrap();
+        assert_eq!(expected, formatted);
+        assert_eq!(Ok(formatted.clone()), format(&formatted, &config));
+
+        let codes = format("print (1;", &config)
+            .unwrap_err()
+            .iter()
+            .map(|diagnostic| diagnostic.code)
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["E0101"], codes);
+    }
 }
@@ -1,58 +1,674 @@
-use std::{env, fs, io::Write, path::Path};
-
-// mod scanner;
-// mod token;
+use std::{
+    cell::RefCell,
+    env,
+    fmt::Display,
+    fs, io,
+    path::PathBuf,
+    process::ExitCode,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
 
+use ckai::args::set_script_args;
+use ckai::chunk::Prototype;
+use ckai::compiler::Compiler;
+use ckai::diagnostics::{Diagnostic, Renderer, Severity, ToDiagnostic};
+use ckai::disassembler::disassemble;
+use ckai::formatter::{self, FormatConfig};
+use ckai::interpreter::Interpreter;
+use ckai::object::GcConfig;
 use ckai::parser::Parser;
+use ckai::repl::{is_complete, Backend, Command, Session};
+use ckai::resolver::Resolver;
 use ckai::scanner::Scanner;
+use ckai::statement::Stmt;
+use ckai::vm::Vm;
 use color_eyre::eyre::Result;
+use rustyline::{
+    completion::Completer, error::ReadlineError, highlight::Highlighter, hint::Hinter,
+    history::FileHistory, validate::Validator, Context, Editor, Helper,
+};
+use yansi::Paint;
+
+const USAGE: &str = "\
+usage: kai [options] [command]
+
+commands:
+  run <file> [-- args...]   run a script, `kai <file>` does the same
+  -e <code> [-- args...]    run code given on the command line
+  repl                      start the interactive prompt, the default
+  tokens <file>             list the tokens of a file
+  ast <file>                print the statements parsed from a file
+  check <file>              report errors in a file without running it
+  fmt <file>...             format files in place, `-` formats standard input
+                            to standard output
+
+  <file> can be `-` to read standard input
+
+options:
+  --no-color                plain diagnostics, also set by NO_COLOR
+  --vm                      run on the bytecode VM instead of the tree-walker,
+                            only the VM frees reference cycles such as an
+                            instance whose method closure captures it
+  --dump-bytecode           print the compiled chunks instead of running them
+  --gc-stress               collect garbage before every VM allocation
+  --check                   fmt: list files that aren't formatted and fail
+                            instead of rewriting them
+  --width <columns>         fmt: wrap lines at this width, 80 by default
+  -h, --help                print this message";
+
+/// Flags accepted anywhere before `--`
+#[derive(Debug, Clone, Copy)]
+struct Options {
+    /// `--no-color` (or the NO_COLOR convention) keeps diagnostics plain for logs
+    color: bool,
+    /// `--vm` runs programs on the bytecode VM instead of the tree-walker
+    vm: bool,
+    /// `--dump-bytecode` prints the compiled chunks instead of running them
+    dump_bytecode: bool,
+    /// `--gc-stress` collects garbage before every VM allocation
+    gc_stress: bool,
+}
+
+/// Where a program comes from
+#[derive(Debug, Clone)]
+enum Input {
+    File(PathBuf),
+    /// `-` instead of a file name
+    Stdin,
+    /// `-e <code>`
+    Code(String),
+}
+
+impl Input {
+    fn new(argument: &str) -> Self {
+        if argument == "-" {
+            Input::Stdin
+        } else {
+            Input::File(PathBuf::from(argument))
+        }
+    }
+
+    /// Name to show in diagnostics
+    fn name(&self) -> String {
+        match self {
+            Input::File(path) => path.display().to_string(),
+            Input::Stdin => "<stdin>".to_string(),
+            Input::Code(_) => "<eval>".to_string(),
+        }
+    }
+
+    fn read(&self) -> Result<String, Failure> {
+        let read = match self {
+            Input::File(path) => fs::read_to_string(path),
+            Input::Stdin => io::read_to_string(io::stdin()),
+            Input::Code(code) => Ok(code.clone()),
+        };
+        read.map_err(|err| {
+            fail(
+                Failure::NoInput,
+                format!("Couldn't read {}: {}", self.name(), err),
+            )
+        })
+    }
+}
+
+/// What kai was asked to do
+#[derive(Debug, Clone)]
+enum Mode {
+    Run {
+        input: Input,
+        args: Vec<String>,
+    },
+    Repl,
+    Tokens(Input),
+    Ast(Input),
+    Check(Input),
+    Fmt {
+        inputs: Vec<Input>,
+        check: bool,
+        config: FormatConfig,
+    },
+    Help,
+}
+
+/// Ways an invocation can fail, each exiting with its sysexits.h code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Failure {
+    /// `fmt --check` found code that isn't formatted, the usual code for
+    /// a check that didn't pass
+    Unformatted = 1,
+    /// The command line doesn't make sense
+    Usage = 64,
+    /// The program has lexical, syntax, static or compile errors
+    Compile = 65,
+    /// The program couldn't be read
+    NoInput = 66,
+    /// The program stopped on a runtime error
+    Runtime = 70,
+    /// The prompt failed to read input or write history, or a formatted
+    /// file couldn't be written
+    Io = 74,
+}
+
+impl From<Failure> for ExitCode {
+    fn from(failure: Failure) -> Self {
+        ExitCode::from(failure as u8)
+    }
+}
+
+/// Reports `message` and gives back `failure`, for `map_err` and `return Err`
+fn fail(failure: Failure, message: impl Display) -> Failure {
+    eprintln!("{}", Paint::red(message));
+    failure
+}
+
+fn main() -> ExitCode {
+    let _ = color_eyre::install();
 
-fn main() -> Result<()> {
-    color_eyre::install()?;
+    let args = env::args().skip(1).collect::<Vec<String>>();
+    let (options, mode) = match parse_args(args) {
+        Ok(parsed) => parsed,
+        Err(message) => {
+            eprintln!("error: {}\n\n{}", message, USAGE);
+            return Failure::Usage.into();
+        }
+    };
+    if !options.color {
+        Paint::disable();
+    }
 
-    let args = env::args().collect::<Vec<String>>();
+    match execute(mode, options) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(failure) => failure.into(),
+    }
+}
 
-    if args.len() > 2 {
-        println!("usage kai [script]!")
-    } else if args.len() == 2 {
-        run_script(Path::new(args[1].as_str()))?;
+/// Splits the command line into options and a mode. Everything after `--`
+/// is left for the script.
+fn parse_args(mut args: Vec<String>) -> Result<(Options, Mode), String> {
+    let script_args = match args.iter().position(|arg| arg == "--") {
+        Some(separator) => args.split_off(separator).split_off(1),
+        None => vec![],
+    };
+    let options = Options {
+        color: !take_flag(&mut args, "--no-color") && env::var_os("NO_COLOR").is_none(),
+        vm: take_flag(&mut args, "--vm"),
+        dump_bytecode: take_flag(&mut args, "--dump-bytecode"),
+        gc_stress: take_flag(&mut args, "--gc-stress"),
+    };
+    if take_flag(&mut args, "-h") || take_flag(&mut args, "--help") {
+        return Ok((options, Mode::Help));
+    }
+    let check = take_flag(&mut args, "--check");
+    let width = take_value(&mut args, "--width")?;
+    if let Some(flag) = args.iter().find(|arg| arg.starts_with("--")) {
+        return Err(format!("Unknown option '{}'", flag));
+    }
+
+    let mut mode = if let Some(position) = args.iter().position(|arg| arg == "-e") {
+        let mut rest = args.split_off(position);
+        if rest.len() < 2 {
+            return Err("'-e' expects the code to run".to_string());
+        }
+        if let Some(extra) = args.first().or(rest.get(2)) {
+            return Err(format!("Unexpected argument '{}'", extra));
+        }
+        Mode::Run {
+            input: Input::Code(rest.swap_remove(1)),
+            args: vec![],
+        }
     } else {
-        run_prompt()?
+        match args.as_slice() {
+            [] => Mode::Repl,
+            [command] if command == "repl" => Mode::Repl,
+            [command, file] if command == "run" => Mode::Run {
+                input: Input::new(file),
+                args: vec![],
+            },
+            [command, file] if command == "tokens" => Mode::Tokens(Input::new(file)),
+            [command, file] if command == "ast" => Mode::Ast(Input::new(file)),
+            [command, file] if command == "check" => Mode::Check(Input::new(file)),
+            [command, files @ ..] if command == "fmt" && !files.is_empty() => {
+                let mut config = FormatConfig::default();
+                if let Some(width) = &width {
+                    config.width = width
+                        .parse()
+                        .map_err(|_| format!("Invalid width '{}'", width))?;
+                }
+                Mode::Fmt {
+                    inputs: files.iter().map(|file| Input::new(file)).collect(),
+                    check,
+                    config,
+                }
+            }
+            [command] if ["run", "tokens", "ast", "check", "fmt"].contains(&command.as_str()) => {
+                return Err(format!("'{}' expects a file", command));
+            }
+            [file] => Mode::Run {
+                input: Input::new(file),
+                args: vec![],
+            },
+            [_, extra, ..] => return Err(format!("Unexpected argument '{}'", extra)),
+        }
+    };
+    if !matches!(mode, Mode::Fmt { .. }) {
+        if check {
+            return Err("'--check' only applies to fmt".to_string());
+        }
+        if width.is_some() {
+            return Err("'--width' only applies to fmt".to_string());
+        }
+    }
+    if options.dump_bytecode && !matches!(mode, Mode::Run { .. }) {
+        return Err("'--dump-bytecode' only applies to running a script".to_string());
+    }
+    if options.gc_stress && !options.vm {
+        return Err("'--gc-stress' only applies with '--vm'".to_string());
+    }
+    match &mut mode {
+        Mode::Run { args, .. } => *args = script_args,
+        _ if !script_args.is_empty() => {
+            return Err("Only scripts take arguments after '--'".to_string());
+        }
+        _ => {}
     }
+    Ok((options, mode))
+}
+
+/// Removes `flag` from `args`, returning whether it was there
+fn take_flag(args: &mut Vec<String>, flag: &str) -> bool {
+    let found = args.iter().any(|arg| arg == flag);
+    args.retain(|arg| arg != flag);
+    found
+}
 
+/// Removes `option` and the value following it from `args`
+fn take_value(args: &mut Vec<String>, option: &str) -> Result<Option<String>, String> {
+    let Some(position) = args.iter().position(|arg| arg == option) else {
+        return Ok(None);
+    };
+    if position + 1 >= args.len() {
+        return Err(format!("'{}' expects a value", option));
+    }
+    let value = args.remove(position + 1);
+    args.remove(position);
+    Ok(Some(value))
+}
+
+fn execute(mode: Mode, options: Options) -> Result<(), Failure> {
+    match mode {
+        Mode::Run { input, args } => {
+            let source = input.read()?;
+            set_script_args(args);
+            run(&source, &input.name(), options)
+        }
+        Mode::Repl => run_prompt(options).map_err(|err| fail(Failure::Io, err)),
+        Mode::Tokens(input) => dump(&input, options, Session::tokens),
+        Mode::Ast(input) => dump(&input, options, Session::ast),
+        Mode::Check(input) => {
+            let source = input.read()?;
+            check(&source, &input.name(), options)
+        }
+        Mode::Fmt {
+            inputs,
+            check,
+            config,
+        } => format(&inputs, check, &config, options),
+        Mode::Help => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+    }
+}
+
+/// Formats each input, rewriting files in place and printing standard
+/// input. With `check` nothing is written, the inputs that would change are
+/// listed instead.
+fn format(
+    inputs: &[Input],
+    check: bool,
+    config: &FormatConfig,
+    options: Options,
+) -> Result<(), Failure> {
+    let mut failure = None;
+    let mut unformatted = 0;
+    for input in inputs {
+        match format_input(input, check, config, options) {
+            Ok(true) => unformatted += 1,
+            Ok(false) => {}
+            Err(err) => failure = failure.or(Some(err)),
+        }
+    }
+
+    if let Some(failure) = failure {
+        return Err(failure);
+    }
+    if check && unformatted > 0 {
+        let message = format!("{} would be reformatted", plural(unformatted, "input"));
+        return Err(fail(Failure::Unformatted, message));
+    }
     Ok(())
 }
 
-fn run_script(script_path: &Path) -> Result<()> {
-    let source_code = fs::read_to_string(script_path).unwrap();
-    println!("source: {}", &source_code);
-    run(&source_code)?;
+/// Formats one input, returning whether that changed it
+fn format_input(
+    input: &Input,
+    check: bool,
+    config: &FormatConfig,
+    options: Options,
+) -> Result<bool, Failure> {
+    let source = input.read()?;
+    let file_name = input.name();
+    let formatted = formatter::format(&source, config).map_err(|diagnostics| {
+        let renderer = Renderer::new(&file_name, &source).with_color(options.color);
+        for diagnostic in &diagnostics {
+            eprint!("{}", renderer.render(diagnostic));
+        }
+        fail(Failure::Compile, format!("Couldn't format {}", file_name))
+    })?;
 
+    let changed = formatted != source;
+    match input {
+        _ if check => {
+            if changed {
+                println!("Would reformat {}", file_name);
+            }
+        }
+        Input::File(path) if changed => fs::write(path, formatted).map_err(|err| {
+            fail(
+                Failure::Io,
+                format!("Couldn't write {}: {}", file_name, err),
+            )
+        })?,
+        Input::File(_) => {}
+        Input::Stdin | Input::Code(_) => print!("{}", formatted),
+    }
+    Ok(changed)
+}
+
+/// Prints the tokens or statements of `input` the way the REPL commands do
+fn dump(
+    input: &Input,
+    options: Options,
+    command: fn(&mut Session, &str) -> Vec<Diagnostic>,
+) -> Result<(), Failure> {
+    let source = input.read()?;
+    let diagnostics = command(&mut Session::new(Backend::TreeWalker), &source);
+    let file_name = input.name();
+    let renderer = Renderer::new(&file_name, &source).with_color(options.color);
+    for diagnostic in &diagnostics {
+        eprint!("{}", renderer.render(diagnostic));
+    }
+
+    let errors = diagnostics
+        .iter()
+        .filter(|diagnostic| diagnostic.severity == Severity::Error)
+        .count();
+    if errors > 0 {
+        return Err(fail(Failure::Compile, found(&[(errors, "error")])));
+    }
     Ok(())
 }
 
-fn run(source_code: &str) -> Result<()> {
-    let mut scanner = Scanner::new(&source_code);
-    scanner.scan_tokens()?;
-    scanner.print_tokens();
+/// Scans, parses and resolves a program, reporting every problem found
+fn analyze<'s>(source_code: &'s str, renderer: &Renderer) -> Result<Vec<Stmt<'s>>, Failure> {
+    let mut scanner = Scanner::new(source_code);
+    // keep going after lexical errors so syntax errors are reported as well
+    let lexical_errors = scanner.scan_tokens().err().unwrap_or_default();
+    report(renderer, &lexical_errors);
+
     let mut parser = Parser::new(scanner.tokens);
-    let exp = parser.expression();
-    println!("Expression {}", exp);
+    let statements = match parser.parse() {
+        Ok(statements) if lexical_errors.is_empty() => statements,
+        Ok(_) => {
+            let message = found(&[(lexical_errors.len(), "lexical error")]);
+            return Err(fail(Failure::Compile, message));
+        }
+        Err(errors) => {
+            report(renderer, &errors);
+            let message = found(&[
+                (lexical_errors.len(), "lexical error"),
+                (errors.len(), "syntax error"),
+            ]);
+            return Err(fail(Failure::Compile, message));
+        }
+    };
+
+    let mut resolver = Resolver::new();
+    let resolved = resolver.resolve(&statements);
+    report(renderer, &resolver.warnings);
+    if let Err(errors) = resolved {
+        report(renderer, &errors);
+        let message = found(&[(errors.len(), "static error")]);
+        return Err(fail(Failure::Compile, message));
+    }
+    Ok(statements)
+}
+
+/// Summarizes how many problems of each kind were found, leaving out the
+/// kinds with none, as in "Found 2 lexical errors and 1 syntax error"
+fn found(counts: &[(usize, &str)]) -> String {
+    let counts = counts
+        .iter()
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, kind)| plural(*count, kind))
+        .collect::<Vec<_>>();
+    format!("Found {}", counts.join(" and "))
+}
+
+/// `count` followed by `noun`, with an `s` unless there is just one
+fn plural(count: usize, noun: &str) -> String {
+    match count {
+        1 => format!("1 {}", noun),
+        _ => format!("{} {}s", count, noun),
+    }
+}
+
+fn compile(statements: &[Stmt], renderer: &Renderer) -> Result<Rc<Prototype>, Failure> {
+    Compiler::new().compile(statements).map_err(|errors| {
+        report(renderer, &errors);
+        let message = found(&[(errors.len(), "compile error")]);
+        fail(Failure::Compile, message)
+    })
+}
+
+/// Reports the problems in a program without running it. With `--vm` the
+/// program is compiled as well, since the bytecode backend has limits of
+/// its own.
+fn check(source_code: &str, file_name: &str, options: Options) -> Result<(), Failure> {
+    let renderer = &Renderer::new(file_name, source_code).with_color(options.color);
+    let statements = analyze(source_code, renderer)?;
+    if options.vm {
+        compile(&statements, renderer)?;
+    }
     Ok(())
 }
 
-fn run_prompt() -> Result<()> {
-    println!("starting kai prompt");
+fn run(source_code: &str, file_name: &str, options: Options) -> Result<(), Failure> {
+    let renderer = &Renderer::new(file_name, source_code).with_color(options.color);
+    let statements = analyze(source_code, renderer)?;
+
+    let result = if options.vm || options.dump_bytecode {
+        let script = compile(&statements, renderer)?;
+        if options.dump_bytecode {
+            print!("{}", disassemble(&script));
+            return Ok(());
+        }
+        let gc = GcConfig {
+            stress: options.gc_stress,
+            ..GcConfig::default()
+        };
+        Vm::new().with_gc(gc).interpret(script)
+    } else {
+        Interpreter::new().interpret(&statements)
+    };
+
+    if let Err(err) = result {
+        report(renderer, &[err]);
+        return Err(fail(Failure::Runtime, "Aborted with a runtime error"));
+    }
+    Ok(())
+}
+
+fn report<D: ToDiagnostic>(renderer: &Renderer, problems: &[D]) {
+    for problem in problems {
+        eprint!("{}", renderer.render(&problem.to_diagnostic()));
+    }
+}
+
+/// Rustyline helper completing reserved words and the session's globals
+struct KaiHelper {
+    session: Rc<RefCell<Session>>,
+}
+
+impl Completer for KaiHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(self.session.borrow().completions(line, pos))
+    }
+}
+
+impl Hinter for KaiHelper {
+    type Hint = String;
+}
+
+impl Highlighter for KaiHelper {}
+
+impl Validator for KaiHelper {}
+
+impl Helper for KaiHelper {}
+
+/// Runs a REPL command, rendering its diagnostics against its argument or
+/// the loaded file
+fn run_command(session: &mut Session, command: Command, options: Options) {
+    let (file_name, source, diagnostics) = match command {
+        Command::Tokens(source) => ("<repl>", source.to_string(), session.tokens(source)),
+        Command::Ast(source) => ("<repl>", source.to_string(), session.ast(source)),
+        Command::Rpn(source) => ("<repl>", source.to_string(), session.rpn(source)),
+        Command::Time(source) => ("<repl>", source.to_string(), session.time(source)),
+        Command::Load(path) => match fs::read_to_string(path) {
+            Ok(source) => {
+                let diagnostics = session.run(&source);
+                (path, source, diagnostics)
+            }
+            Err(err) => {
+                eprintln!(
+                    "{}",
+                    Paint::red(format!("Couldn't read '{}': {}", path, err))
+                );
+                return;
+            }
+        },
+        Command::Reset => {
+            session.reset();
+            return;
+        }
+        Command::Help => {
+            println!("{}", Command::HELP);
+            return;
+        }
+    };
+
+    let renderer = Renderer::new(file_name, &source).with_color(options.color);
+    for diagnostic in diagnostics {
+        eprint!("{}", renderer.render(&diagnostic));
+    }
+}
+
+fn run_prompt(options: Options) -> Result<()> {
+    println!("starting kai prompt, type :help for commands");
+    let backend = if options.vm {
+        Backend::Vm(GcConfig {
+            stress: options.gc_stress,
+            ..GcConfig::default()
+        })
+    } else {
+        Backend::TreeWalker
+    };
+    // Ctrl-C while a program runs stops it, at the prompt the editor sees it
+    // as a key press instead
+    let interrupt = Arc::new(AtomicBool::new(false));
+    let handler_interrupt = Arc::clone(&interrupt);
+    ctrlc::set_handler(move || handler_interrupt.store(true, Ordering::Relaxed))?;
+    let session = Rc::new(RefCell::new(
+        Session::new(backend).with_interrupt(Arc::clone(&interrupt)),
+    ));
+
+    let mut editor: Editor<KaiHelper, FileHistory> = Editor::new()?;
+    editor.set_helper(Some(KaiHelper {
+        session: Rc::clone(&session),
+    }));
+    let history = dirs::home_dir().map(|home| home.join(".kai_history"));
+    if let Some(history) = &history {
+        // there is no history file before the first session
+        let _ = editor.load_history(history);
+    }
+
+    // lines are gathered until they make a complete input
+    let mut input = String::new();
     loop {
-        let mut input = String::new();
-        print!("kai> ");
-        let _ = std::io::stdout().flush()?;
-        std::io::stdin().read_line(&mut input)?;
+        let prompt = if input.is_empty() { "kai> " } else { "...> " };
+        match editor.readline(prompt) {
+            Ok(line) => {
+                input.push_str(&line);
+                input.push('\n');
+            }
+            Err(ReadlineError::Interrupted) => {
+                input.clear();
+                continue;
+            }
+            Err(ReadlineError::Eof) => break,
+            Err(err) => return Err(err.into()),
+        }
 
-        if input == "exit\n" {
+        if input.trim() == "exit" {
             break;
         }
-        run(&input)?
+        // a Ctrl-C pressed earlier must not stop the command or code run next
+        interrupt.store(false, Ordering::Relaxed);
+        if input.starts_with(':') {
+            editor.add_history_entry(input.trim_end())?;
+            let line = std::mem::take(&mut input);
+            match Command::parse(&line) {
+                Ok(command) => run_command(&mut session.borrow_mut(), command, options),
+                Err(err) => eprint!(
+                    "{}",
+                    Renderer::new("<repl>", "")
+                        .with_color(options.color)
+                        .render(&err.to_diagnostic())
+                ),
+            }
+            continue;
+        }
+        if !is_complete(&input) {
+            continue;
+        }
+
+        editor.add_history_entry(input.trim_end())?;
+        let renderer = Renderer::new("<repl>", &input).with_color(options.color);
+        for diagnostic in session.borrow_mut().eval(&input) {
+            eprint!("{}", renderer.render(&diagnostic));
+        }
+        input.clear();
+    }
+
+    if let Some(history) = &history {
+        if let Err(err) = editor.save_history(history) {
+            eprintln!(
+                "{}",
+                Paint::yellow(format!("Couldn't save history: {}", err))
+            );
+        }
     }
     Ok(())
 }
@@ -0,0 +1,80 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{args, number::Number};
+
+/// What natives see of the values of either backend, and what they return
+/// to it. Each backend converts its own values from and into these.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NativeValue {
+    Nil,
+    Bool(bool),
+    Number(Number),
+    String(String),
+    /// Functions, classes, instances and the like, which no native reads
+    Other,
+}
+
+/// A function implemented in Rust and available to every kai program, on
+/// either backend. Callers check the arity before calling it.
+#[derive(Debug, Clone, Copy)]
+pub struct Native {
+    pub name: &'static str,
+    pub arity: usize,
+    function: fn(&[NativeValue]) -> NativeValue,
+}
+
+impl Native {
+    pub fn all() -> Vec<Native> {
+        vec![
+            Native {
+                name: "clock",
+                arity: 0,
+                function: clock,
+            },
+            Native {
+                name: "argc",
+                arity: 0,
+                function: argc,
+            },
+            Native {
+                name: "arg",
+                arity: 1,
+                function: arg,
+            },
+        ]
+    }
+
+    pub fn call(&self, arguments: &[NativeValue]) -> NativeValue {
+        (self.function)(arguments)
+    }
+}
+
+impl PartialEq for Native {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+/// Seconds since the unix epoch
+fn clock(_arguments: &[NativeValue]) -> NativeValue {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    NativeValue::Number(Number::Float(now.as_secs_f64()))
+}
+
+/// Number of arguments given to the script
+fn argc(_arguments: &[NativeValue]) -> NativeValue {
+    NativeValue::Number(Number::Integer(args::script_arg_count() as i64))
+}
+
+/// The script argument at the given index, `nil` when there is none
+fn arg(arguments: &[NativeValue]) -> NativeValue {
+    let argument = match arguments {
+        [NativeValue::Number(Number::Integer(index))] => {
+            usize::try_from(*index).ok().and_then(args::script_arg)
+        }
+        _ => None,
+    };
+    argument.map_or(NativeValue::Nil, NativeValue::String)
+}
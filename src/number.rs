@@ -0,0 +1,105 @@
+use std::{cmp::Ordering, fmt::Display};
+
+/// Numbers shared by the tree-walker and the VM. Integers stay exact until
+/// they meet a float, then the result is a float.
+#[derive(Debug, Clone, Copy)]
+pub enum Number {
+    Integer(i64),
+    Float(f64),
+}
+
+/// Ways integer arithmetic can fail, float arithmetic never does
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArithmeticError {
+    Overflow,
+    DivisionByZero,
+}
+
+impl Number {
+    pub fn as_f64(self) -> f64 {
+        match self {
+            Number::Integer(val) => val as f64,
+            Number::Float(val) => val,
+        }
+    }
+
+    pub fn checked_add(self, other: Number) -> Result<Number, ArithmeticError> {
+        self.combine(other, i64::checked_add, |l, r| l + r)
+    }
+
+    pub fn checked_sub(self, other: Number) -> Result<Number, ArithmeticError> {
+        self.combine(other, i64::checked_sub, |l, r| l - r)
+    }
+
+    pub fn checked_mul(self, other: Number) -> Result<Number, ArithmeticError> {
+        self.combine(other, i64::checked_mul, |l, r| l * r)
+    }
+
+    /// Integer division stays an integer when it is exact, otherwise the
+    /// result is a float, so `10 / 4` is `2.5`
+    pub fn checked_div(self, other: Number) -> Result<Number, ArithmeticError> {
+        match (self, other) {
+            (Number::Integer(_), Number::Integer(0)) => Err(ArithmeticError::DivisionByZero),
+            (Number::Integer(l), Number::Integer(r))
+                if l.checked_rem(r).is_some_and(|rem| rem != 0) =>
+            {
+                Ok(Number::Float(l as f64 / r as f64))
+            }
+            _ => self.combine(other, i64::checked_div, |l, r| l / r),
+        }
+    }
+
+    pub fn checked_neg(self) -> Result<Number, ArithmeticError> {
+        match self {
+            Number::Integer(val) => val
+                .checked_neg()
+                .map(Number::Integer)
+                .ok_or(ArithmeticError::Overflow),
+            Number::Float(val) => Ok(Number::Float(-val)),
+        }
+    }
+
+    fn combine(
+        self,
+        other: Number,
+        integer: fn(i64, i64) -> Option<i64>,
+        float: fn(f64, f64) -> f64,
+    ) -> Result<Number, ArithmeticError> {
+        match (self, other) {
+            (Number::Integer(l), Number::Integer(r)) => integer(l, r)
+                .map(Number::Integer)
+                .ok_or(ArithmeticError::Overflow),
+            (l, r) => Ok(Number::Float(float(l.as_f64(), r.as_f64()))),
+        }
+    }
+}
+
+/// Integers and floats compare by value, so `1 == 1.0`
+impl PartialEq for Number {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Number::Integer(l), Number::Integer(r)) => l == r,
+            (l, r) => l.as_f64() == r.as_f64(),
+        }
+    }
+}
+
+impl PartialOrd for Number {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match (self, other) {
+            (Number::Integer(l), Number::Integer(r)) => l.partial_cmp(r),
+            (l, r) => l.as_f64().partial_cmp(&r.as_f64()),
+        }
+    }
+}
+
+/// Floats always show a fractional part or an exponent, so `3.0` can be
+/// told apart from `3`
+impl Display for Number {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Number::Integer(val) => write!(f, "{}", val),
+            Number::Float(val) => write!(f, "{:?}", val),
+        }
+    }
+}
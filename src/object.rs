@@ -0,0 +1,317 @@
+use std::{collections::HashMap, fmt::Display, rc::Rc};
+
+use crate::{
+    chunk::Prototype,
+    native::{Native, NativeValue},
+    number::Number,
+};
+
+/// Handle to an object living in the VM [`Heap`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ObjRef(usize);
+
+/// Values manipulated by the VM. Strings are immutable and can't form
+/// cycles, so they are shared directly instead of living in the heap.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Nil,
+    Bool(bool),
+    Number(Number),
+    String(Rc<str>),
+    Native(Native),
+    Object(ObjRef),
+}
+
+impl Value {
+    /// `nil` and `false` are falsy, everything else is truthy
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Value::Nil | Value::Bool(false))
+    }
+}
+
+impl From<&Value> for NativeValue {
+    fn from(value: &Value) -> Self {
+        match value {
+            Value::Nil => NativeValue::Nil,
+            Value::Bool(val) => NativeValue::Bool(*val),
+            Value::Number(val) => NativeValue::Number(*val),
+            Value::String(val) => NativeValue::String(val.to_string()),
+            Value::Native(_) | Value::Object(_) => NativeValue::Other,
+        }
+    }
+}
+
+impl From<NativeValue> for Value {
+    fn from(value: NativeValue) -> Self {
+        match value {
+            NativeValue::Bool(val) => Value::Bool(val),
+            NativeValue::Number(val) => Value::Number(val),
+            NativeValue::String(val) => Value::String(val.into()),
+            NativeValue::Nil | NativeValue::Other => Value::Nil,
+        }
+    }
+}
+
+/// A function together with the variables it captured
+#[derive(Debug)]
+pub struct Closure {
+    pub function: Rc<Prototype>,
+    pub upvalues: Vec<ObjRef>,
+}
+
+/// A captured variable. It points into the stack while the variable is in
+/// scope and holds the value itself once the variable is gone.
+#[derive(Debug)]
+pub enum Upvalue {
+    Open(usize),
+    Closed(Value),
+}
+
+#[derive(Debug)]
+pub struct Class {
+    pub name: Rc<str>,
+    pub methods: HashMap<Rc<str>, ObjRef>,
+}
+
+#[derive(Debug)]
+pub struct Instance {
+    pub class: ObjRef,
+    pub fields: HashMap<Rc<str>, Value>,
+}
+
+/// A method closure with `this` bound to `receiver`
+#[derive(Debug)]
+pub struct BoundMethod {
+    pub receiver: Value,
+    pub method: ObjRef,
+}
+
+#[derive(Debug)]
+pub enum Object {
+    Closure(Closure),
+    Upvalue(Upvalue),
+    Class(Class),
+    Instance(Instance),
+    BoundMethod(BoundMethod),
+}
+
+impl Object {
+    /// Pushes every object directly reachable from this one onto `gray`
+    fn references(&self, gray: &mut Vec<ObjRef>) {
+        match self {
+            Object::Closure(closure) => gray.extend(&closure.upvalues),
+            Object::Upvalue(Upvalue::Open(_)) => {}
+            Object::Upvalue(Upvalue::Closed(value)) => push_value(gray, value),
+            Object::Class(class) => gray.extend(class.methods.values()),
+            Object::Instance(instance) => {
+                gray.push(instance.class);
+                for value in instance.fields.values() {
+                    push_value(gray, value);
+                }
+            }
+            Object::BoundMethod(bound) => {
+                push_value(gray, &bound.receiver);
+                gray.push(bound.method);
+            }
+        }
+    }
+}
+
+fn push_value(gray: &mut Vec<ObjRef>, value: &Value) {
+    if let Value::Object(reference) = value {
+        gray.push(*reference);
+    }
+}
+
+/// When the [`Heap`] asks for a collection. Sizes are counted in objects.
+#[derive(Debug, Clone, Copy)]
+pub struct GcConfig {
+    /// Live objects allowed before the first collection
+    pub initial_threshold: usize,
+    /// After a collection the next one happens once the live objects grew
+    /// by this factor
+    pub growth_factor: usize,
+    /// Collect before every allocation, to shake out missing roots
+    pub stress: bool,
+}
+
+impl Default for GcConfig {
+    fn default() -> Self {
+        Self {
+            initial_threshold: 1024,
+            growth_factor: 2,
+            stress: false,
+        }
+    }
+}
+
+/// Storage for every object the VM allocates, reclaimed by mark and sweep.
+/// The heap doesn't know the roots: the VM marks them before calling
+/// [`Heap::collect`].
+#[derive(Debug, Default)]
+pub struct Heap {
+    objects: Vec<Option<Object>>,
+    marked: Vec<bool>,
+    /// Slots of freed objects, reused by the next allocations
+    free: Vec<usize>,
+    /// Marked objects whose references haven't been traced yet
+    gray: Vec<ObjRef>,
+    live: usize,
+    next_gc: usize,
+    config: GcConfig,
+}
+
+impl Heap {
+    pub fn new() -> Self {
+        Self::with_config(GcConfig::default())
+    }
+
+    pub fn with_config(config: GcConfig) -> Self {
+        Self {
+            next_gc: config.initial_threshold,
+            config,
+            ..Self::default()
+        }
+    }
+
+    pub fn alloc(&mut self, object: Object) -> ObjRef {
+        self.live += 1;
+        match self.free.pop() {
+            Some(index) => {
+                self.objects[index] = Some(object);
+                ObjRef(index)
+            }
+            None => {
+                self.objects.push(Some(object));
+                self.marked.push(false);
+                ObjRef(self.objects.len() - 1)
+            }
+        }
+    }
+
+    /// Number of objects allocated and not freed yet
+    pub fn live(&self) -> usize {
+        self.live
+    }
+
+    pub fn should_collect(&self) -> bool {
+        self.config.stress || self.live >= self.next_gc
+    }
+
+    pub fn mark(&mut self, reference: ObjRef) {
+        self.gray.push(reference);
+    }
+
+    pub fn mark_value(&mut self, value: &Value) {
+        push_value(&mut self.gray, value);
+    }
+
+    /// Marks what `object` refers to, for an object that is about to be
+    /// allocated and so can't be reached from the roots yet
+    pub fn mark_references(&mut self, object: &Object) {
+        object.references(&mut self.gray);
+    }
+
+    /// Frees every object that wasn't reached from the marked roots
+    pub fn collect(&mut self) {
+        while let Some(reference) = self.gray.pop() {
+            if std::mem::replace(&mut self.marked[reference.0], true) {
+                continue;
+            }
+            if let Some(object) = &self.objects[reference.0] {
+                object.references(&mut self.gray);
+            }
+        }
+
+        for (index, object) in self.objects.iter_mut().enumerate() {
+            if std::mem::take(&mut self.marked[index]) || object.is_none() {
+                continue;
+            }
+            *object = None;
+            self.free.push(index);
+            self.live -= 1;
+        }
+
+        self.next_gc = (self.live * self.config.growth_factor).max(self.config.initial_threshold);
+    }
+
+    pub fn get(&self, reference: ObjRef) -> &Object {
+        match &self.objects[reference.0] {
+            Some(object) => object,
+            None => panic!("use of freed object {:?}", reference),
+        }
+    }
+
+    pub fn get_mut(&mut self, reference: ObjRef) -> &mut Object {
+        match &mut self.objects[reference.0] {
+            Some(object) => object,
+            None => panic!("use of freed object {:?}", reference),
+        }
+    }
+
+    // the compiler decides what each handle points to, so a mismatch in the
+    // accessors below is a bug in the VM rather than in the kai program
+
+    pub fn closure(&self, reference: ObjRef) -> &Closure {
+        match self.get(reference) {
+            Object::Closure(closure) => closure,
+            object => panic!("expected a closure, found {:?}", object),
+        }
+    }
+
+    pub fn upvalue_mut(&mut self, reference: ObjRef) -> &mut Upvalue {
+        match self.get_mut(reference) {
+            Object::Upvalue(upvalue) => upvalue,
+            object => panic!("expected an upvalue, found {:?}", object),
+        }
+    }
+
+    pub fn class_mut(&mut self, reference: ObjRef) -> &mut Class {
+        match self.get_mut(reference) {
+            Object::Class(class) => class,
+            object => panic!("expected a class, found {:?}", object),
+        }
+    }
+
+    /// Formats `value` the way `print` shows it
+    pub fn display<'h>(&'h self, value: &'h Value) -> impl Display + 'h {
+        Printable { heap: self, value }
+    }
+}
+
+struct Printable<'h> {
+    heap: &'h Heap,
+    value: &'h Value,
+}
+
+impl<'h> Display for Printable<'h> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let reference = match self.value {
+            Value::Nil => return write!(f, "nil"),
+            Value::Bool(val) => return write!(f, "{}", val),
+            Value::Number(val) => return write!(f, "{}", val),
+            Value::String(val) => return write!(f, "{}", val),
+            Value::Native(native) => return write!(f, "<native fn {}>", native.name),
+            Value::Object(reference) => *reference,
+        };
+
+        match self.heap.get(reference) {
+            Object::Closure(closure) if closure.function.name.is_empty() => write!(f, "<script>"),
+            Object::Closure(closure) => write!(f, "<fn {}>", closure.function.name),
+            Object::Upvalue(_) => write!(f, "<upvalue>"),
+            Object::Class(class) => write!(f, "{}", class.name),
+            Object::Instance(instance) => match self.heap.get(instance.class) {
+                Object::Class(class) => write!(f, "{} instance", class.name),
+                _ => write!(f, "instance"),
+            },
+            Object::BoundMethod(bound) => {
+                let method = Value::Object(bound.method);
+                let printable = Printable {
+                    heap: self.heap,
+                    value: &method,
+                };
+                write!(f, "{}", printable)
+            }
+        }
+    }
+}
@@ -1,181 +1,861 @@
+use std::{cell::Cell, collections::HashMap, rc::Rc};
+
+use thiserror::Error;
+
 use crate::{
+    cst::{Event, SyntaxKind},
     expression::Expression,
-    token::{Token, TokenType},
+    statement::{ClassDecl, FunctionDecl, Stmt},
+    token::{Span, Token, TokenType},
 };
 
+#[derive(Debug, Clone, Error, PartialEq)]
+pub enum ParseError {
+    #[error("{expected}, found '{found}'")]
+    UnexpectedToken {
+        expected: String,
+        found: String,
+        span: Span,
+    },
+    #[error("Expected expression, found '{found}'")]
+    ExpectedExpression { found: String, span: Span },
+    #[error("Invalid assignment target")]
+    InvalidAssignmentTarget { span: Span },
+}
+
 #[derive(Debug)]
 pub struct Parser<'a> {
     pub tokens: Vec<Token<'a>>,
     pub curr_idx: usize,
+    errors: Vec<ParseError>,
+    block_depth: usize,
+    /// Doc comments, keyed by the index of the token right after them
+    docs: HashMap<usize, Vec<Token<'a>>>,
+    /// The nodes parsed so far, for [`crate::cst::parse`] to build the
+    /// concrete syntax tree from
+    pub events: Vec<Event>,
+}
+
+/// Where a node that is only recognised later, like the left operand of a
+/// binary expression, would start
+#[derive(Debug, Clone, Copy)]
+struct Marker {
+    event: usize,
+    position: usize,
+}
+
+/// Whether the grammar sees `token`, rather than skipping it as trivia
+pub fn is_significant(token: &Token) -> bool {
+    !matches!(
+        token.token_type,
+        TokenType::NewLine | TokenType::Whitespace | TokenType::Comment | TokenType::DocComment
+    )
 }
 
 impl<'a> Parser<'a> {
     pub fn new(tokens: Vec<Token<'a>>) -> Self {
+        // newlines and other trivia are not significant to the grammar and
+        // doc comments are set aside until a declaration picks them up
+        let mut kept = vec![];
+        let mut docs = HashMap::new();
+        let mut pending = vec![];
+        for token in tokens {
+            if is_significant(&token) {
+                if !pending.is_empty() {
+                    docs.insert(kept.len(), std::mem::take(&mut pending));
+                }
+                kept.push(token);
+            } else if token.token_type == TokenType::DocComment {
+                pending.push(token);
+            }
+        }
+
         Parser {
-            tokens,
+            tokens: kept,
             curr_idx: 0,
+            errors: vec![],
+            block_depth: 0,
+            docs,
+            events: vec![],
         }
     }
 
+    /// Opens a node at the current token. Nodes left open by a syntax error
+    /// are closed when the parser synchronizes.
+    fn start(&mut self, kind: SyntaxKind) {
+        self.events.push(Event::Start(kind, self.curr_idx));
+    }
+
+    /// Closes the last node opened, after the tokens consumed so far
+    fn finish(&mut self) {
+        self.events.push(Event::Finish(self.curr_idx));
+    }
+
+    fn marker(&self) -> Marker {
+        Marker {
+            event: self.events.len(),
+            position: self.curr_idx,
+        }
+    }
+
+    /// Opens a node at `marker`, around everything parsed since
+    fn start_at(&mut self, marker: Marker, kind: SyntaxKind) {
+        self.events
+            .insert(marker.event, Event::Start(kind, marker.position));
+    }
+
     fn is_at_end(&self) -> bool {
-        self.curr_idx >= self.tokens.len()
+        self.curr_idx >= self.tokens.len() || self.current().token_type == TokenType::Eof
+    }
+
+    fn check(&self, token_type: TokenType) -> bool {
+        !self.is_at_end() && self.current().token_type == token_type
+    }
+
+    /// Consumes the current token if it is of `token_type`
+    fn match_token(&mut self, token_type: TokenType) -> bool {
+        if self.check(token_type) {
+            self.curr_idx += 1;
+            return true;
+        }
+        false
+    }
+
+    fn consume(&mut self, token_type: TokenType, message: &str) -> Result<Token<'a>, ParseError> {
+        if self.check(token_type) {
+            self.curr_idx += 1;
+            return Ok(self.prev());
+        }
+
+        let (found, span) = self.found();
+        Err(ParseError::UnexpectedToken {
+            expected: message.to_string(),
+            found,
+            span,
+        })
+    }
+
+    /// Describes the current token for error messages
+    fn found(&self) -> (String, Span) {
+        match self.tokens.get(self.curr_idx) {
+            Some(token) if token.token_type != TokenType::Eof => {
+                (token.lexeme.to_string(), token.span)
+            }
+            Some(token) => ("end of file".to_string(), token.span),
+            None => ("end of file".to_string(), self.prev().span),
+        }
     }
 
     fn prev(&self) -> Token<'a> {
-        self.tokens[self.curr_idx - 1]
+        self.tokens[self.curr_idx - 1].clone()
     }
 
-    fn current(&self) -> Token<'a> {
-        self.tokens[self.curr_idx]
+    fn current(&self) -> &Token<'a> {
+        &self.tokens[self.curr_idx]
     }
 
-    pub fn expression(&mut self) -> Expression<'a> {
-        self.ternary()
+    /// Parses a whole program, collecting every syntax error instead of
+    /// stopping at the first one
+    pub fn parse(&mut self) -> Result<Vec<Stmt<'a>>, Vec<ParseError>> {
+        let mut statements = vec![];
+        while !self.is_at_end() {
+            if let Some(statement) = self.synchronized_declaration() {
+                statements.push(statement);
+            }
+        }
+
+        if self.errors.is_empty() {
+            Ok(statements)
+        } else {
+            Err(std::mem::take(&mut self.errors))
+        }
     }
 
-    fn ternary(&mut self) -> Expression<'a> {
-        let exp = self.equality();
-        if self.is_at_end() {
-            return exp;
+    /// Parses a declaration, recording any error and skipping ahead to the
+    /// next statement boundary so parsing can continue
+    fn synchronized_declaration(&mut self) -> Option<Stmt<'a>> {
+        let marker = self.marker();
+        match self.declaration() {
+            Ok(statement) => Some(statement),
+            Err(err) => {
+                // a lexeme that failed to scan was reported already, and the
+                // tokens right around it are bound to look wrong
+                let near_lexical_error = [self.curr_idx.checked_sub(1), Some(self.curr_idx)]
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|idx| self.tokens.get(idx))
+                    .any(|token| token.token_type == TokenType::Error);
+                if !near_lexical_error {
+                    self.errors.push(err);
+                }
+                // the nodes left open by the failure end where it happened,
+                // and an error node holds them along with the tokens skipped
+                let open = self.events[marker.event..]
+                    .iter()
+                    .map(|event| match event {
+                        Event::Start(..) => 1,
+                        Event::Finish(_) => -1,
+                    })
+                    .sum::<isize>();
+                for _ in 0..open {
+                    self.finish();
+                }
+                self.start_at(marker, SyntaxKind::Error);
+                self.synchronize();
+                self.finish();
+                None
+            }
         }
-        if self.current().token_type == TokenType::Question {
+    }
+
+    fn synchronize(&mut self) {
+        // a '}' closing the enclosing block is left for the block to consume
+        let closes_block =
+            |parser: &Self| parser.block_depth > 0 && parser.check(TokenType::RightBrace);
+
+        if closes_block(self) {
+            return;
+        }
+        if !self.is_at_end() {
             self.curr_idx += 1;
-            if self.is_at_end() {
-                panic!("No expression found after '?' operator, expected valid ternary expression");
-            } else {
-                let exp1 = self.equality();
-                if self.current().token_type == TokenType::Colon {
-                    self.curr_idx += 1;
-                    let exp2 = self.equality();
-                    return Expression::Ternary(Box::new(exp), Box::new(exp1), Box::new(exp2));
-                } else {
-                    panic!("Expected valid ternary expression, expected :");
+        }
+
+        while !self.is_at_end() {
+            if self.prev().token_type == TokenType::SemiColon || closes_block(self) {
+                return;
+            }
+
+            match self.current().token_type {
+                TokenType::Class
+                | TokenType::Fun
+                | TokenType::Var
+                | TokenType::For
+                | TokenType::If
+                | TokenType::While
+                | TokenType::Print
+                | TokenType::Return => return,
+                _ => self.curr_idx += 1,
+            }
+        }
+    }
+
+    /// Takes the doc comments written right before the current token
+    fn take_docs(&mut self) -> Vec<Token<'a>> {
+        self.docs.remove(&self.curr_idx).unwrap_or_default()
+    }
+
+    fn declaration(&mut self) -> Result<Stmt<'a>, ParseError> {
+        // only declarations keep their doc comments, those before a statement are dropped
+        let docs = self.take_docs();
+        if self.check(TokenType::Var) {
+            return self.var_declaration(docs);
+        }
+        if self.check(TokenType::Fun) {
+            self.start(SyntaxKind::FunDecl);
+            self.curr_idx += 1;
+            let keyword = self.prev();
+            let mut declaration = self.function("function", docs)?;
+            declaration.span = keyword.span.to(declaration.span);
+            self.finish();
+            return Ok(Stmt::Function(Rc::new(declaration)));
+        }
+        if self.check(TokenType::Class) {
+            return self.class_declaration(docs);
+        }
+        self.statement()
+    }
+
+    fn class_declaration(&mut self, docs: Vec<Token<'a>>) -> Result<Stmt<'a>, ParseError> {
+        self.start(SyntaxKind::ClassDecl);
+        self.curr_idx += 1;
+        let keyword = self.prev();
+        let name = self.consume(TokenType::Identifier, "Expected class name")?;
+
+        let superclass = if self.check(TokenType::Less) {
+            self.start(SyntaxKind::Superclass);
+            self.curr_idx += 1;
+            let superclass = self.consume(TokenType::Identifier, "Expected superclass name")?;
+            self.finish();
+            Some(Expression::Variable(superclass, Cell::new(None)))
+        } else {
+            None
+        };
+
+        self.consume(TokenType::LeftBrace, "Expected '{' before class body")?;
+        let mut methods = vec![];
+        while !self.is_at_end() && !self.check(TokenType::RightBrace) {
+            let docs = self.take_docs();
+            self.start(SyntaxKind::Method);
+            methods.push(Rc::new(self.function("method", docs)?));
+            self.finish();
+        }
+        let right_brace = self.consume(TokenType::RightBrace, "Expected '}' after class body")?;
+        self.finish();
+
+        Ok(Stmt::Class(ClassDecl {
+            docs,
+            name,
+            superclass,
+            methods,
+            span: keyword.span.to(right_brace.span),
+        }))
+    }
+
+    fn function(
+        &mut self,
+        kind: &str,
+        docs: Vec<Token<'a>>,
+    ) -> Result<FunctionDecl<'a>, ParseError> {
+        let name = self.consume(TokenType::Identifier, &format!("Expected {} name", kind))?;
+        self.start(SyntaxKind::ParamList);
+        self.consume(
+            TokenType::LeftParen,
+            &format!("Expected '(' after {} name", kind),
+        )?;
+
+        let mut params = vec![];
+        if !self.check(TokenType::RightParen) {
+            loop {
+                params.push(self.consume(TokenType::Identifier, "Expected parameter name")?);
+                if !self.match_token(TokenType::Comma) {
+                    break;
                 }
             }
         }
+        self.consume(TokenType::RightParen, "Expected ')' after parameters")?;
+        self.finish();
+
+        self.consume(
+            TokenType::LeftBrace,
+            &format!("Expected '{{' before {} body", kind),
+        )?;
+        let (body, body_span) = self.block()?;
 
-        exp
+        Ok(FunctionDecl {
+            span: name.span.to(body_span),
+            docs,
+            name,
+            params,
+            body,
+        })
     }
 
-    fn equality(&mut self) -> Expression<'a> {
-        let mut exp = self.comparison();
+    fn var_declaration(&mut self, docs: Vec<Token<'a>>) -> Result<Stmt<'a>, ParseError> {
+        self.start(SyntaxKind::VarDecl);
+        self.curr_idx += 1;
+        let name = self.consume(TokenType::Identifier, "Expected variable name")?;
 
-        if self.is_at_end() {
-            return exp;
+        let initializer = if self.match_token(TokenType::Equal) {
+            Some(self.expression()?)
+        } else {
+            None
+        };
+
+        self.consume(
+            TokenType::SemiColon,
+            "Expected ';' after variable declaration",
+        )?;
+        self.finish();
+        Ok(Stmt::Var(name, initializer, docs))
+    }
+
+    fn statement(&mut self) -> Result<Stmt<'a>, ParseError> {
+        if self.check(TokenType::If) {
+            return self.if_statement();
         }
 
-        while match self.current().token_type {
-            TokenType::NotEqual | TokenType::EqualEqual => {
-                self.curr_idx += 1;
-                true
+        if self.check(TokenType::While) {
+            return self.while_statement();
+        }
+
+        if self.check(TokenType::For) {
+            return self.for_statement();
+        }
+
+        if self.check(TokenType::Return) {
+            return self.return_statement();
+        }
+
+        if self.check(TokenType::Print) {
+            self.start(SyntaxKind::PrintStmt);
+            self.curr_idx += 1;
+            let keyword = self.prev();
+            let exp = self.expression()?;
+            self.consume(TokenType::SemiColon, "Expected ';' after value")?;
+            self.finish();
+            return Ok(Stmt::Print(keyword, exp));
+        }
+
+        if self.match_token(TokenType::LeftBrace) {
+            let (statements, span) = self.block()?;
+            return Ok(Stmt::Block(statements, span));
+        }
+
+        self.expression_statement()
+    }
+
+    fn expression_statement(&mut self) -> Result<Stmt<'a>, ParseError> {
+        self.start(SyntaxKind::ExprStmt);
+        let exp = self.expression()?;
+        self.consume(TokenType::SemiColon, "Expected ';' after expression")?;
+        self.finish();
+        Ok(Stmt::Expression(exp))
+    }
+
+    fn return_statement(&mut self) -> Result<Stmt<'a>, ParseError> {
+        self.start(SyntaxKind::ReturnStmt);
+        self.curr_idx += 1;
+        let keyword = self.prev();
+        let value = if self.check(TokenType::SemiColon) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+
+        self.consume(TokenType::SemiColon, "Expected ';' after return value")?;
+        self.finish();
+        Ok(Stmt::Return(keyword, value))
+    }
+
+    fn if_statement(&mut self) -> Result<Stmt<'a>, ParseError> {
+        self.start(SyntaxKind::IfStmt);
+        self.curr_idx += 1;
+        let keyword = self.prev();
+        self.consume(TokenType::LeftParen, "Expected '(' after 'if'")?;
+        let condition = self.expression()?;
+        self.consume(TokenType::RightParen, "Expected ')' after if condition")?;
+
+        let then_branch = Box::new(self.statement()?);
+        let else_branch = if self.match_token(TokenType::Else) {
+            Some(Box::new(self.statement()?))
+        } else {
+            None
+        };
+
+        self.finish();
+        Ok(Stmt::If(keyword, condition, then_branch, else_branch))
+    }
+
+    fn while_statement(&mut self) -> Result<Stmt<'a>, ParseError> {
+        self.start(SyntaxKind::WhileStmt);
+        self.curr_idx += 1;
+        let keyword = self.prev();
+        self.consume(TokenType::LeftParen, "Expected '(' after 'while'")?;
+        let condition = self.expression()?;
+        self.consume(TokenType::RightParen, "Expected ')' after while condition")?;
+
+        let body = self.statement()?;
+        self.finish();
+        Ok(Stmt::While(keyword, condition, Box::new(body)))
+    }
+
+    /// `for` has no node of its own, it is desugared into a `while` loop
+    fn for_statement(&mut self) -> Result<Stmt<'a>, ParseError> {
+        self.start(SyntaxKind::ForStmt);
+        self.curr_idx += 1;
+        let keyword = self.prev();
+        self.consume(TokenType::LeftParen, "Expected '(' after 'for'")?;
+
+        let initializer = if self.match_token(TokenType::SemiColon) {
+            None
+        } else if self.check(TokenType::Var) {
+            Some(self.var_declaration(vec![])?)
+        } else {
+            Some(self.expression_statement()?)
+        };
+
+        let condition = if self.check(TokenType::SemiColon) {
+            // an empty condition is an always true literal, spanning nothing
+            let span = self.current().span;
+            let span = Span::new(span.start, span.start, span.line, span.column);
+            Expression::Literal(Token::new(TokenType::True, "", span))
+        } else {
+            self.expression()?
+        };
+        self.consume(TokenType::SemiColon, "Expected ';' after loop condition")?;
+
+        let increment = if self.check(TokenType::RightParen) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.consume(TokenType::RightParen, "Expected ')' after for clauses")?;
+
+        let mut body = self.statement()?;
+        self.finish();
+        if let Some(increment) = increment {
+            let span = body.span().to(increment.span());
+            body = Stmt::Block(vec![body, Stmt::Expression(increment)], span);
+        }
+        body = Stmt::While(keyword, condition, Box::new(body));
+
+        Ok(match initializer {
+            Some(initializer) => {
+                let span = body.span();
+                Stmt::Block(vec![initializer, body], span)
+            }
+            None => body,
+        })
+    }
+
+    /// Parses the rest of a block after its '{', returning the statements
+    /// and the span from brace to brace
+    fn block(&mut self) -> Result<(Vec<Stmt<'a>>, Span), ParseError> {
+        let left_brace = self.prev();
+        // the node starts at the '{' the caller consumed
+        self.events
+            .push(Event::Start(SyntaxKind::Block, self.curr_idx - 1));
+        let mut statements = vec![];
+        self.block_depth += 1;
+        while !self.is_at_end() && !self.check(TokenType::RightBrace) {
+            if let Some(statement) = self.synchronized_declaration() {
+                statements.push(statement);
             }
-            _ => false,
-        } {
+        }
+        self.block_depth -= 1;
+
+        let right_brace = self.consume(TokenType::RightBrace, "Expected '}' after block")?;
+        self.finish();
+        Ok((statements, left_brace.span.to(right_brace.span)))
+    }
+
+    /// Parses an expression that has to make up all the tokens
+    pub fn whole_expression(&mut self) -> Result<Expression<'a>, ParseError> {
+        let exp = self.expression()?;
+        if !self.is_at_end() {
+            let (found, span) = self.found();
+            return Err(ParseError::UnexpectedToken {
+                expected: "Expected end of expression".to_string(),
+                found,
+                span,
+            });
+        }
+        Ok(exp)
+    }
+
+    pub fn expression(&mut self) -> Result<Expression<'a>, ParseError> {
+        self.assignment()
+    }
+
+    fn assignment(&mut self) -> Result<Expression<'a>, ParseError> {
+        let marker = self.marker();
+        let exp = self.ternary()?;
+
+        if self.check(TokenType::Equal) {
+            self.start_at(marker, SyntaxKind::AssignExpr);
+            self.curr_idx += 1;
+            let value = self.assignment()?;
+            self.finish();
+
+            return match exp {
+                Expression::Variable(name, _) => {
+                    Ok(Expression::Assign(name, Box::new(value), Cell::new(None)))
+                }
+                Expression::Get(object, name) => Ok(Expression::Set(object, name, Box::new(value))),
+                // the parser is not confused here, so report without unwinding
+                _ => {
+                    self.errors
+                        .push(ParseError::InvalidAssignmentTarget { span: exp.span() });
+                    Ok(exp)
+                }
+            };
+        }
+
+        Ok(exp)
+    }
+
+    fn ternary(&mut self) -> Result<Expression<'a>, ParseError> {
+        let marker = self.marker();
+        let exp = self.logic_or()?;
+
+        if self.check(TokenType::Question) {
+            self.start_at(marker, SyntaxKind::TernaryExpr);
+            self.curr_idx += 1;
+            let exp1 = self.logic_or()?;
+            self.consume(TokenType::Colon, "Expected ':' in ternary expression")?;
+            let exp2 = self.logic_or()?;
+            self.finish();
+            return Ok(Expression::Ternary(
+                Box::new(exp),
+                Box::new(exp1),
+                Box::new(exp2),
+            ));
+        }
+
+        Ok(exp)
+    }
+
+    fn logic_or(&mut self) -> Result<Expression<'a>, ParseError> {
+        let marker = self.marker();
+        let mut exp = self.logic_and()?;
+
+        while self.check(TokenType::Or) {
+            self.start_at(marker, SyntaxKind::LogicalExpr);
+            self.curr_idx += 1;
             let operator = self.prev();
-            let right = self.comparison();
-            exp = Expression::Binary(Box::new(exp), operator.token_type, Box::new(right));
+            let right = self.logic_and()?;
+            self.finish();
+            exp = Expression::Logical(Box::new(exp), operator, Box::new(right));
         }
+        Ok(exp)
+    }
 
-        exp
+    fn logic_and(&mut self) -> Result<Expression<'a>, ParseError> {
+        let marker = self.marker();
+        let mut exp = self.equality()?;
+
+        while self.check(TokenType::And) {
+            self.start_at(marker, SyntaxKind::LogicalExpr);
+            self.curr_idx += 1;
+            let operator = self.prev();
+            let right = self.equality()?;
+            self.finish();
+            exp = Expression::Logical(Box::new(exp), operator, Box::new(right));
+        }
+        Ok(exp)
     }
 
-    fn comparison(&mut self) -> Expression<'a> {
-        let mut exp = self.term();
+    fn equality(&mut self) -> Result<Expression<'a>, ParseError> {
+        let marker = self.marker();
+        let mut exp = self.comparison()?;
+
         if self.is_at_end() {
-            return exp;
+            return Ok(exp);
         }
 
-        while match self.current().token_type {
-            TokenType::Greater
-            | TokenType::GreaterEqual
-            | TokenType::Less
-            | TokenType::LessEqual => {
-                self.curr_idx += 1;
-                true
-            }
-            _ => false,
-        } {
+        while matches!(
+            self.current().token_type,
+            TokenType::NotEqual | TokenType::EqualEqual
+        ) {
+            self.start_at(marker, SyntaxKind::BinaryExpr);
+            self.curr_idx += 1;
             let operator = self.prev();
-            let right = self.term();
-            exp = Expression::Binary(Box::new(exp), operator.token_type, Box::new(right));
+            let right = self.comparison()?;
+            self.finish();
+            exp = Expression::Binary(Box::new(exp), operator, Box::new(right));
         }
-        exp
+
+        Ok(exp)
     }
 
-    fn term(&mut self) -> Expression<'a> {
-        let mut exp = self.factor();
+    fn comparison(&mut self) -> Result<Expression<'a>, ParseError> {
+        let marker = self.marker();
+        let mut exp = self.term()?;
+        if self.is_at_end() {
+            return Ok(exp);
+        }
+
+        while matches!(
+            self.current().token_type,
+            TokenType::Greater | TokenType::GreaterEqual | TokenType::Less | TokenType::LessEqual
+        ) {
+            self.start_at(marker, SyntaxKind::BinaryExpr);
+            self.curr_idx += 1;
+            let operator = self.prev();
+            let right = self.term()?;
+            self.finish();
+            exp = Expression::Binary(Box::new(exp), operator, Box::new(right));
+        }
+        Ok(exp)
+    }
+
+    fn term(&mut self) -> Result<Expression<'a>, ParseError> {
+        let marker = self.marker();
+        let mut exp = self.factor()?;
 
         if self.is_at_end() {
-            return exp;
+            return Ok(exp);
         }
 
-        while match self.current().token_type {
-            TokenType::Plus | TokenType::Minus => {
-                self.curr_idx += 1;
-                true
-            }
-            _ => false,
-        } {
+        while matches!(
+            self.current().token_type,
+            TokenType::Plus | TokenType::Minus
+        ) {
+            self.start_at(marker, SyntaxKind::BinaryExpr);
+            self.curr_idx += 1;
             let operator = self.prev();
-            let right = self.factor();
-            exp = Expression::Binary(Box::new(exp), operator.token_type, Box::new(right));
+            let right = self.factor()?;
+            self.finish();
+            exp = Expression::Binary(Box::new(exp), operator, Box::new(right));
         }
-        exp
+        Ok(exp)
     }
-    fn factor(&mut self) -> Expression<'a> {
-        let mut exp = self.unary();
+    fn factor(&mut self) -> Result<Expression<'a>, ParseError> {
+        let marker = self.marker();
+        let mut exp = self.unary()?;
         if self.is_at_end() {
-            return exp;
+            return Ok(exp);
         }
 
-        while match self.current().token_type {
-            TokenType::Slash | TokenType::Star => {
-                self.curr_idx += 1;
-                true
-            }
-            _ => false,
-        } {
+        while matches!(
+            self.current().token_type,
+            TokenType::Slash | TokenType::Star
+        ) {
+            self.start_at(marker, SyntaxKind::BinaryExpr);
+            self.curr_idx += 1;
             let operator = self.prev();
-            let right = self.unary();
-            exp = Expression::Binary(Box::new(exp), operator.token_type, Box::new(right));
+            let right = self.unary()?;
+            self.finish();
+            exp = Expression::Binary(Box::new(exp), operator, Box::new(right));
         }
-        exp
+        Ok(exp)
     }
-    fn unary(&mut self) -> Expression<'a> {
-        match self.current().token_type {
-            TokenType::Not | TokenType::Minus => {
-                self.curr_idx += 1;
-                let operator = self.prev().token_type;
-                let exp = self.unary();
+    fn unary(&mut self) -> Result<Expression<'a>, ParseError> {
+        if self.check(TokenType::Not) || self.check(TokenType::Minus) {
+            self.start(SyntaxKind::UnaryExpr);
+            self.curr_idx += 1;
+            let operator = self.prev();
+            let exp = self.unary()?;
+            self.finish();
 
-                Expression::Unary(operator, Box::new(exp))
+            return Ok(Expression::Unary(operator, Box::new(exp)));
+        }
+        self.call()
+    }
+
+    fn call(&mut self) -> Result<Expression<'a>, ParseError> {
+        let marker = self.marker();
+        let mut exp = self.primary()?;
+
+        loop {
+            if self.check(TokenType::LeftParen) {
+                self.start_at(marker, SyntaxKind::CallExpr);
+                exp = self.finish_call(exp)?;
+                self.finish();
+            } else if self.check(TokenType::Dot) {
+                self.start_at(marker, SyntaxKind::FieldExpr);
+                self.curr_idx += 1;
+                let name =
+                    self.consume(TokenType::Identifier, "Expected property name after '.'")?;
+                self.finish();
+                exp = Expression::Get(Box::new(exp), name);
+            } else {
+                break;
             }
-            _ => self.primary(),
         }
+        Ok(exp)
     }
 
-    fn primary(&mut self) -> Expression<'a> {
-        if self.is_at_end() {
-            return Expression::Literal(TokenType::Eof);
+    /// Parses the arguments of a call, from its '(' on
+    fn finish_call(&mut self, callee: Expression<'a>) -> Result<Expression<'a>, ParseError> {
+        self.start(SyntaxKind::ArgList);
+        self.curr_idx += 1;
+        let mut arguments = vec![];
+        if !self.check(TokenType::RightParen) {
+            loop {
+                arguments.push(self.expression()?);
+                if !self.match_token(TokenType::Comma) {
+                    break;
+                }
+            }
         }
 
+        let paren = self.consume(TokenType::RightParen, "Expected ')' after arguments")?;
+        self.finish();
+        Ok(Expression::Call(Box::new(callee), paren, arguments))
+    }
+
+    fn primary(&mut self) -> Result<Expression<'a>, ParseError> {
+        let kind = match self
+            .tokens
+            .get(self.curr_idx)
+            .map(|token| &token.token_type)
+        {
+            Some(
+                TokenType::Number(_)
+                | TokenType::Integer(_)
+                | TokenType::String(_)
+                | TokenType::True
+                | TokenType::False
+                | TokenType::Nil,
+            ) => SyntaxKind::Literal,
+            // a lexeme that failed to scan, already reported by the scanner
+            Some(TokenType::Error) => SyntaxKind::Error,
+            Some(TokenType::Identifier) => SyntaxKind::NameRef,
+            Some(TokenType::This) => SyntaxKind::ThisExpr,
+            Some(TokenType::Super) => SyntaxKind::SuperExpr,
+            Some(TokenType::Interpolation(_)) => SyntaxKind::InterpolatedString,
+            Some(TokenType::LeftParen) => SyntaxKind::GroupExpr,
+            // the offending token is left for synchronization to skip
+            _ => {
+                let (found, span) = self.found();
+                return Err(ParseError::ExpectedExpression { found, span });
+            }
+        };
+
+        self.start(kind);
         self.curr_idx += 1;
-        match self.prev().token_type {
-            TokenType::Number(val) => Expression::Literal(TokenType::Number(val)),
-            TokenType::String(val) => Expression::Literal(TokenType::String(val)),
-            TokenType::Identifier => Expression::Literal(TokenType::Identifier),
+        let token = self.prev();
+        let exp = match token.token_type {
+            TokenType::Identifier => Expression::Variable(token, Cell::new(None)),
+            TokenType::This => Expression::This(token, Cell::new(None)),
+            TokenType::Super => {
+                self.consume(TokenType::Dot, "Expected '.' after 'super'")?;
+                let method =
+                    self.consume(TokenType::Identifier, "Expected superclass method name")?;
+                Expression::Super(token, method, Cell::new(None))
+            }
+            TokenType::Interpolation(_) => self.interpolation()?,
             TokenType::LeftParen => {
-                let exp = self.equality();
+                let exp = self.expression()?;
+                let right_paren =
+                    self.consume(TokenType::RightParen, "Expected ')' after expression")?;
+                Expression::Grouping(Box::new(exp), token.span.to(right_paren.span))
+            }
+            _ => Expression::Literal(token),
+        };
+        self.finish();
 
-                if self.current().token_type != TokenType::RightParen {
-                    panic!("Expected ')' at line: {}", self.prev().line);
-                } else {
-                    self.curr_idx += 1;
-                    Expression::Grouping(Box::new(exp))
-                }
+        Ok(exp)
+    }
+
+    /// Parses the rest of an interpolated string whose first part was just
+    /// consumed: each interpolated expression is followed by the next part,
+    /// up to the [`TokenType::InterpolationEnd`]
+    fn interpolation(&mut self) -> Result<Expression<'a>, ParseError> {
+        let mut parts = vec![string_part(self.prev())];
+        loop {
+            parts.push(self.expression()?);
+
+            let closed = matches!(
+                self.tokens
+                    .get(self.curr_idx)
+                    .map(|token| &token.token_type),
+                Some(TokenType::InterpolationPart(_) | TokenType::InterpolationEnd(_))
+            );
+            if !closed {
+                let (found, span) = self.found();
+                return Err(ParseError::UnexpectedToken {
+                    expected: "Expected '}' after interpolated expression".to_string(),
+                    found,
+                    span,
+                });
+            }
+
+            self.curr_idx += 1;
+            let part = self.prev();
+            let last = matches!(part.token_type, TokenType::InterpolationEnd(_));
+            parts.push(string_part(part));
+            if last {
+                break;
             }
-            _ if self.is_at_end() => Expression::Literal(TokenType::Eof),
-            _ => panic!("Unexpected token at line: {}", self.prev().line),
         }
+
+        let span = parts[0].span().to(parts[parts.len() - 1].span());
+        Ok(Expression::Interpolation(parts, span))
     }
 }
+
+/// Turns a piece of an interpolated string into a string literal
+fn string_part(token: Token) -> Expression {
+    let token_type = match token.token_type {
+        TokenType::Interpolation(contents)
+        | TokenType::InterpolationPart(contents)
+        | TokenType::InterpolationEnd(contents) => TokenType::String(contents),
+        token_type => token_type,
+    };
+    Expression::Literal(Token {
+        token_type,
+        ..token
+    })
+}
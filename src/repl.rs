@@ -0,0 +1,405 @@
+use std::{
+    cell::RefCell,
+    io::{self, Write},
+    rc::Rc,
+    sync::{atomic::AtomicBool, Arc},
+    time::Instant,
+};
+
+use thiserror::Error;
+
+use crate::{
+    compiler::Compiler,
+    diagnostics::{Diagnostic, Severity, ToDiagnostic},
+    expression::{Expression, ReversePolish},
+    interpreter::{Interpreter, RuntimeError},
+    object::GcConfig,
+    parser::Parser,
+    resolver::Resolver,
+    scanner::{Scanner, ScannerError},
+    statement::{static_statements, Stmt},
+    token::{Token, TokenType},
+    vm::Vm,
+};
+
+/// Which evaluator a [`Session`] runs its inputs on
+#[derive(Debug, Clone, Copy)]
+pub enum Backend {
+    TreeWalker,
+    Vm(GcConfig),
+}
+
+enum Engine {
+    TreeWalker(Interpreter<'static>),
+    Vm(Box<Vm>),
+}
+
+/// Output shared by the evaluator and the session's own commands, so a
+/// reset evaluator can keep writing to it
+#[derive(Clone)]
+struct SharedOutput(Rc<RefCell<Box<dyn Write>>>);
+
+impl Write for SharedOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.borrow_mut().flush()
+    }
+}
+
+/// REPL commands, written `:name` followed by their argument
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command<'s> {
+    /// `:tokens <source>` lists the tokens of `source`
+    Tokens(&'s str),
+    /// `:ast <source>` prints the statements parsed from `source`
+    Ast(&'s str),
+    /// `:rpn <expression>` prints an expression in reverse polish notation
+    Rpn(&'s str),
+    /// `:load <path>` runs a file in the session
+    Load(&'s str),
+    /// `:reset` forgets everything defined so far
+    Reset,
+    /// `:time <source>` runs `source` and reports how long it took
+    Time(&'s str),
+    /// `:help` lists the commands
+    Help,
+}
+
+/// Problems with a REPL command line
+#[derive(Debug, Clone, Error, PartialEq)]
+pub enum CommandError {
+    #[error("Unknown command '{name}'")]
+    Unknown { name: String },
+    #[error("Command '{name}' expects an argument")]
+    MissingArgument { name: String },
+}
+
+impl<'s> Command<'s> {
+    pub const HELP: &'static str = "\
+:tokens <source>   list the tokens of source
+:ast <source>      print the statements parsed from source
+:rpn <expression>  print an expression in reverse polish notation
+:load <path>       run a file in the session
+:reset             forget everything defined so far
+:time <source>     run source and report how long it took
+:help              list these commands
+exit               leave the prompt";
+
+    /// Parses a line starting with `:`
+    pub fn parse(line: &'s str) -> Result<Self, CommandError> {
+        let line = line.trim();
+        let (name, argument) = match line.split_once(char::is_whitespace) {
+            Some((name, argument)) => (name, argument.trim()),
+            None => (line, ""),
+        };
+        let with_argument = |command: fn(&'s str) -> Command<'s>| {
+            if argument.is_empty() {
+                Err(CommandError::MissingArgument {
+                    name: name.to_string(),
+                })
+            } else {
+                Ok(command(argument))
+            }
+        };
+
+        match name {
+            ":tokens" => with_argument(Command::Tokens),
+            ":ast" => with_argument(Command::Ast),
+            ":rpn" => with_argument(Command::Rpn),
+            ":load" => with_argument(Command::Load),
+            ":time" => with_argument(Command::Time),
+            ":reset" => Ok(Command::Reset),
+            ":help" => Ok(Command::Help),
+            _ => Err(CommandError::Unknown {
+                name: name.to_string(),
+            }),
+        }
+    }
+}
+
+/// An interactive session: globals, functions and classes defined by one
+/// input are still there for the next. An input ending with an expression
+/// written without its `;` has the value of that expression printed.
+pub struct Session {
+    backend: Backend,
+    output: SharedOutput,
+    interrupt: Arc<AtomicBool>,
+    engine: Engine,
+}
+
+impl Session {
+    pub fn new(backend: Backend) -> Self {
+        Self::with_output(backend, Box::new(io::stdout()))
+    }
+
+    /// Creates a session that writes `print` output, echoed values and
+    /// command results to `output`
+    pub fn with_output(backend: Backend, output: Box<dyn Write>) -> Self {
+        let output = SharedOutput(Rc::new(RefCell::new(output)));
+        let interrupt = Arc::default();
+        Session {
+            engine: engine(backend, &output, &interrupt),
+            backend,
+            output,
+            interrupt,
+        }
+    }
+
+    /// Lets a raised `interrupt` stop the input being run, see
+    /// [`Interpreter::with_interrupt`]
+    pub fn with_interrupt(mut self, interrupt: Arc<AtomicBool>) -> Self {
+        self.interrupt = interrupt;
+        self.reset();
+        self
+    }
+
+    /// Forgets every global defined so far
+    pub fn reset(&mut self) {
+        self.engine = engine(self.backend, &self.output, &self.interrupt);
+    }
+
+    /// Completions for the word ending at byte `pos` of `line`: reserved
+    /// words and the globals defined so far that start with it. Returns
+    /// where the word starts along with the candidates, in order.
+    pub fn completions(&self, line: &str, pos: usize) -> (usize, Vec<String>) {
+        let start = line[..pos]
+            .char_indices()
+            .rev()
+            .take_while(|(_, c)| *c == '_' || c.is_alphanumeric())
+            .last()
+            .map_or(pos, |(idx, _)| idx);
+        let prefix = &line[start..pos];
+        if prefix.is_empty() {
+            return (pos, vec![]);
+        }
+
+        let mut words = match &self.engine {
+            Engine::TreeWalker(interpreter) => interpreter.global_names(),
+            Engine::Vm(vm) => vm.global_names(),
+        };
+        words.extend(
+            Scanner::new("")
+                .reserved
+                .keys()
+                .map(|word| word.to_string()),
+        );
+        words.retain(|word| word.starts_with(prefix));
+        words.sort();
+        words.dedup();
+        (start, words)
+    }
+
+    /// Runs one complete input, see [`is_complete`], returning the problems
+    /// found in it. Nothing runs if any of them is an error.
+    pub fn eval(&mut self, source: &str) -> Vec<Diagnostic> {
+        match missing_semicolon(source) {
+            Some(end) => {
+                let mut source = source.to_string();
+                source.insert(end, ';');
+                self.execute(&source, true)
+            }
+            None => self.execute(source, false),
+        }
+    }
+
+    /// Runs `source` the way a script runs, without echoing anything, which
+    /// is how `:load` runs a file
+    pub fn run(&mut self, source: &str) -> Vec<Diagnostic> {
+        self.execute(source, false)
+    }
+
+    fn execute(&mut self, source: &str, echo: bool) -> Vec<Diagnostic> {
+        let mut scanner = Scanner::new(source);
+        let mut diagnostics = to_diagnostics(&scanner.scan_tokens().err().unwrap_or_default());
+        // definitions outlive the input they come from, so the statements
+        // are copied to own their text
+        let mut statements = match Parser::new(scanner.tokens).parse() {
+            Ok(statements) => static_statements(&statements),
+            Err(errors) => {
+                diagnostics.extend(to_diagnostics(&errors));
+                return diagnostics;
+            }
+        };
+
+        let mut resolver = Resolver::new();
+        let resolved = resolver.resolve(&statements);
+        diagnostics.extend(to_diagnostics(&resolver.warnings));
+        if let Err(errors) = resolved {
+            diagnostics.extend(to_diagnostics(&errors));
+        }
+        if has_errors(&diagnostics) {
+            return diagnostics;
+        }
+
+        if echo {
+            echo_last_expression(&mut statements);
+        }
+        let result = match &mut self.engine {
+            Engine::TreeWalker(interpreter) => interpreter.interpret(&statements),
+            Engine::Vm(vm) => match Compiler::new().compile(&statements) {
+                Ok(script) => vm.interpret(script),
+                Err(errors) => {
+                    diagnostics.extend(to_diagnostics(&errors));
+                    return diagnostics;
+                }
+            },
+        };
+        if let Err(err) = result {
+            diagnostics.push(err.to_diagnostic());
+        }
+        diagnostics
+    }
+
+    /// Lists the tokens of `source`, one per line with its position
+    pub fn tokens(&mut self, source: &str) -> Vec<Diagnostic> {
+        let mut scanner = Scanner::new(source);
+        let mut diagnostics = to_diagnostics(&scanner.scan_tokens().err().unwrap_or_default());
+        let tokens = scanner
+            .tokens
+            .iter()
+            .map(|token| {
+                let position = format!("{}:{}", token.span.line, token.span.column);
+                let token_type = format!("{:?}", token.token_type);
+                format!("{:<7} {:<20} {:?}\n", position, token_type, token.lexeme)
+            })
+            .collect::<String>();
+        diagnostics.extend(self.write(&tokens));
+        diagnostics
+    }
+
+    /// Prints the statements parsed from `source`, one per line
+    pub fn ast(&mut self, source: &str) -> Vec<Diagnostic> {
+        let mut scanner = Scanner::new(source);
+        let mut diagnostics = to_diagnostics(&scanner.scan_tokens().err().unwrap_or_default());
+        match Parser::new(scanner.tokens).parse() {
+            Ok(statements) => {
+                let ast = statements
+                    .iter()
+                    .map(|statement| format!("{}\n", statement))
+                    .collect::<String>();
+                diagnostics.extend(self.write(&ast));
+            }
+            Err(errors) => diagnostics.extend(to_diagnostics(&errors)),
+        }
+        diagnostics
+    }
+
+    /// Prints the expression `source` in reverse polish notation
+    pub fn rpn(&mut self, source: &str) -> Vec<Diagnostic> {
+        let mut scanner = Scanner::new(source);
+        let mut diagnostics = to_diagnostics(&scanner.scan_tokens().err().unwrap_or_default());
+        match Parser::new(scanner.tokens).whole_expression() {
+            Ok(expression) => {
+                let rpn = format!("{}\n", expression.print_rpn());
+                diagnostics.extend(self.write(&rpn));
+            }
+            Err(err) => diagnostics.push(err.to_diagnostic()),
+        }
+        diagnostics
+    }
+
+    /// Runs `source` like [`Session::eval`], then reports how long it took
+    pub fn time(&mut self, source: &str) -> Vec<Diagnostic> {
+        let started = Instant::now();
+        let mut diagnostics = self.eval(source);
+        let elapsed = started.elapsed();
+        diagnostics.extend(self.write(&format!("took {:?}\n", elapsed)));
+        diagnostics
+    }
+
+    fn write(&mut self, text: &str) -> Option<Diagnostic> {
+        self.output
+            .write_all(text.as_bytes())
+            .err()
+            .map(|err| RuntimeError::Output(err.to_string()).to_diagnostic())
+    }
+}
+
+/// Whether `source` can be run as it is, or is still missing its end: an
+/// unclosed `(` or `{`, string, block comment or interpolation. The REPL
+/// keeps reading lines until the input is complete.
+pub fn is_complete(source: &str) -> bool {
+    let mut scanner = Scanner::new(source);
+    let errors = scanner.scan_tokens().err().unwrap_or_default();
+    if errors.iter().any(|err| {
+        matches!(
+            err,
+            ScannerError::UnterminatedString { .. }
+                | ScannerError::UnterminatedComment { .. }
+                | ScannerError::UnterminatedInterpolation { .. }
+        )
+    }) {
+        return false;
+    }
+
+    let mut depth = 0isize;
+    for token in &scanner.tokens {
+        match token.token_type {
+            TokenType::LeftParen | TokenType::LeftBrace => depth += 1,
+            TokenType::RightParen | TokenType::RightBrace => depth -= 1,
+            _ => {}
+        }
+    }
+    depth <= 0
+}
+
+/// Byte offset right after the last token of `source` when that token can't
+/// end a statement, so the `;` of a bare expression can be left out
+fn missing_semicolon(source: &str) -> Option<usize> {
+    let mut scanner = Scanner::new(source);
+    let _ = scanner.scan_tokens();
+    let last = scanner
+        .tokens
+        .iter()
+        .rev()
+        .find(|token| !matches!(token.token_type, TokenType::Eof | TokenType::NewLine))?;
+
+    match last.token_type {
+        TokenType::SemiColon | TokenType::RightBrace | TokenType::DocComment => None,
+        _ => Some(last.span.end),
+    }
+}
+
+/// Turns a trailing expression statement into a `print`, so its value is
+/// shown. Assignments are left alone, they are statements in spirit.
+fn echo_last_expression(statements: &mut Vec<Stmt>) {
+    let echoed = matches!(
+        statements.last(),
+        Some(Stmt::Expression(exp)) if !matches!(exp, Expression::Assign(..) | Expression::Set(..))
+    );
+    if !echoed {
+        return;
+    }
+    if let Some(Stmt::Expression(exp)) = statements.pop() {
+        let keyword = Token::new(TokenType::Print, "", exp.span());
+        statements.push(Stmt::Print(keyword, exp));
+    }
+}
+
+fn to_diagnostics<D: ToDiagnostic>(problems: &[D]) -> Vec<Diagnostic> {
+    problems.iter().map(ToDiagnostic::to_diagnostic).collect()
+}
+
+fn has_errors(diagnostics: &[Diagnostic]) -> bool {
+    diagnostics
+        .iter()
+        .any(|diagnostic| diagnostic.severity == Severity::Error)
+}
+
+fn engine(backend: Backend, output: &SharedOutput, interrupt: &Arc<AtomicBool>) -> Engine {
+    let output = Box::new(output.clone());
+    let interrupt = Arc::clone(interrupt);
+    match backend {
+        Backend::TreeWalker => {
+            Engine::TreeWalker(Interpreter::with_output(output).with_interrupt(interrupt))
+        }
+        Backend::Vm(config) => Engine::Vm(Box::new(
+            Vm::with_output(output)
+                .with_gc(config)
+                .with_interrupt(interrupt),
+        )),
+    }
+}
@@ -0,0 +1,381 @@
+use std::{borrow::Cow, collections::HashMap, fmt::Display};
+
+use thiserror::Error;
+
+use crate::{
+    expression::{Depth, Expression},
+    statement::{ClassDecl, FunctionDecl, Stmt},
+    token::{Span, Token},
+};
+
+#[derive(Debug, Clone, Error, PartialEq)]
+pub enum ResolveError {
+    #[error("Can't read local variable '{name}' in its own initializer")]
+    ReadInOwnInitializer { name: String, span: Span },
+    #[error("Variable '{name}' is already declared in this scope")]
+    DuplicateLocal { name: String, span: Span },
+    #[error("Can't return from top-level code")]
+    TopLevelReturn { span: Span },
+    #[error("Can't return a value from an initializer")]
+    ReturnFromInitializer { span: Span },
+    #[error("Can't use 'this' outside of a class")]
+    ThisOutsideClass { span: Span },
+    #[error("Can't use 'super' outside of a class")]
+    SuperOutsideClass { span: Span },
+    #[error("Can't use 'super' in a class with no superclass")]
+    SuperWithoutSuperclass { span: Span },
+    #[error("A class can't inherit from itself")]
+    InheritFromSelf { span: Span },
+}
+
+/// Problems that do not stop a program from running
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResolveWarning {
+    UnusedLocal { name: String, span: Span },
+}
+
+impl Display for ResolveWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnusedLocal { name, .. } => {
+                write!(f, "Local variable '{}' is never used", name)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FunctionKind {
+    None,
+    Function,
+    Method,
+    Initializer,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ClassKind {
+    None,
+    Class,
+    Subclass,
+}
+
+/// What the resolver knows about a local variable
+#[derive(Debug)]
+struct Binding<'a> {
+    token: Token<'a>,
+    defined: bool,
+    used: bool,
+}
+
+/// Static pass that binds every local variable use to the scope declaring it
+#[derive(Debug)]
+pub struct Resolver<'a> {
+    scopes: Vec<HashMap<Cow<'a, str>, Binding<'a>>>,
+    function: FunctionKind,
+    class: ClassKind,
+    errors: Vec<ResolveError>,
+    pub warnings: Vec<ResolveWarning>,
+}
+
+impl<'a> Default for Resolver<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> Resolver<'a> {
+    pub fn new() -> Self {
+        Resolver {
+            scopes: vec![],
+            function: FunctionKind::None,
+            class: ClassKind::None,
+            errors: vec![],
+            warnings: vec![],
+        }
+    }
+
+    /// Resolves a whole program, returning every static error found
+    pub fn resolve(&mut self, statements: &[Stmt<'a>]) -> Result<(), Vec<ResolveError>> {
+        self.resolve_statements(statements);
+
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(std::mem::take(&mut self.errors))
+        }
+    }
+
+    fn resolve_statements(&mut self, statements: &[Stmt<'a>]) {
+        for statement in statements {
+            self.resolve_statement(statement);
+        }
+    }
+
+    fn resolve_statement(&mut self, statement: &Stmt<'a>) {
+        match statement {
+            Stmt::Expression(exp) | Stmt::Print(_, exp) => self.resolve_expression(exp),
+            Stmt::Var(name, initializer, _) => {
+                self.declare(name);
+                if let Some(initializer) = initializer {
+                    self.resolve_expression(initializer);
+                }
+                self.define(name);
+            }
+            Stmt::Block(statements, _) => {
+                self.begin_scope();
+                self.resolve_statements(statements);
+                self.end_scope();
+            }
+            Stmt::If(_, condition, then_branch, else_branch) => {
+                self.resolve_expression(condition);
+                self.resolve_statement(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.resolve_statement(else_branch);
+                }
+            }
+            Stmt::While(_, condition, body) => {
+                self.resolve_expression(condition);
+                self.resolve_statement(body);
+            }
+            Stmt::Function(declaration) => {
+                self.declare(&declaration.name);
+                self.define(&declaration.name);
+                self.mark_used(&declaration.name.lexeme);
+                self.resolve_function(declaration, FunctionKind::Function);
+            }
+            Stmt::Class(declaration) => self.resolve_class(declaration),
+            Stmt::Return(keyword, value) => {
+                if self.function == FunctionKind::None {
+                    self.errors
+                        .push(ResolveError::TopLevelReturn { span: keyword.span });
+                }
+
+                if let Some(value) = value {
+                    if self.function == FunctionKind::Initializer {
+                        self.errors
+                            .push(ResolveError::ReturnFromInitializer { span: keyword.span });
+                    }
+                    self.resolve_expression(value);
+                }
+            }
+        }
+    }
+
+    fn resolve_function(&mut self, declaration: &FunctionDecl<'a>, kind: FunctionKind) {
+        let enclosing = std::mem::replace(&mut self.function, kind);
+
+        self.begin_scope();
+        for param in &declaration.params {
+            self.declare(param);
+            self.define(param);
+            self.mark_used(&param.lexeme);
+        }
+        self.resolve_statements(&declaration.body);
+        self.end_scope();
+
+        self.function = enclosing;
+    }
+
+    fn resolve_class(&mut self, declaration: &ClassDecl<'a>) {
+        let enclosing = std::mem::replace(&mut self.class, ClassKind::Class);
+
+        self.declare(&declaration.name);
+        self.define(&declaration.name);
+        self.mark_used(&declaration.name.lexeme);
+
+        if let Some(superclass) = &declaration.superclass {
+            if let Expression::Variable(name, _) = superclass {
+                if name.lexeme == declaration.name.lexeme {
+                    self.errors
+                        .push(ResolveError::InheritFromSelf { span: name.span });
+                }
+            }
+
+            self.class = ClassKind::Subclass;
+            self.resolve_expression(superclass);
+            self.begin_scope();
+            self.define_implicit("super", &declaration.name);
+        }
+
+        self.begin_scope();
+        self.define_implicit("this", &declaration.name);
+
+        for method in &declaration.methods {
+            let kind = if method.name.lexeme == "init" {
+                FunctionKind::Initializer
+            } else {
+                FunctionKind::Method
+            };
+            self.resolve_function(method, kind);
+        }
+
+        self.end_scope();
+        if declaration.superclass.is_some() {
+            self.end_scope();
+        }
+
+        self.class = enclosing;
+    }
+
+    fn resolve_expression(&mut self, expression: &Expression<'a>) {
+        match expression {
+            Expression::Literal(_) => {}
+            Expression::Variable(name, depth) => {
+                let in_own_initializer = self
+                    .scopes
+                    .last()
+                    .and_then(|scope| scope.get(&*name.lexeme))
+                    .is_some_and(|binding| !binding.defined);
+
+                if in_own_initializer {
+                    self.errors.push(ResolveError::ReadInOwnInitializer {
+                        name: name.lexeme.to_string(),
+                        span: name.span,
+                    });
+                }
+
+                self.resolve_local(name, depth);
+            }
+            Expression::Assign(name, value, depth) => {
+                self.resolve_expression(value);
+                self.resolve_local(name, depth);
+            }
+            Expression::Binary(left, _, right) | Expression::Logical(left, _, right) => {
+                self.resolve_expression(left);
+                self.resolve_expression(right);
+            }
+            Expression::Unary(_, exp) | Expression::Grouping(exp, _) => {
+                self.resolve_expression(exp)
+            }
+            Expression::Ternary(condition, then_exp, else_exp) => {
+                self.resolve_expression(condition);
+                self.resolve_expression(then_exp);
+                self.resolve_expression(else_exp);
+            }
+            Expression::Call(callee, _, arguments) => {
+                self.resolve_expression(callee);
+                for argument in arguments {
+                    self.resolve_expression(argument);
+                }
+            }
+            Expression::Get(object, _) => self.resolve_expression(object),
+            Expression::Set(object, _, value) => {
+                self.resolve_expression(value);
+                self.resolve_expression(object);
+            }
+            Expression::This(keyword, depth) => {
+                if self.class == ClassKind::None {
+                    self.errors
+                        .push(ResolveError::ThisOutsideClass { span: keyword.span });
+                    return;
+                }
+                self.resolve_local(keyword, depth);
+            }
+            Expression::Super(keyword, _, depth) => {
+                match self.class {
+                    ClassKind::None => self
+                        .errors
+                        .push(ResolveError::SuperOutsideClass { span: keyword.span }),
+                    ClassKind::Class => self
+                        .errors
+                        .push(ResolveError::SuperWithoutSuperclass { span: keyword.span }),
+                    ClassKind::Subclass => {}
+                }
+                self.resolve_local(keyword, depth);
+            }
+            Expression::Interpolation(parts, _) => {
+                for part in parts {
+                    self.resolve_expression(part);
+                }
+            }
+        }
+    }
+
+    /// Records how many scopes separate `name` from its declaration
+    fn resolve_local(&mut self, name: &Token<'a>, depth: &Depth) {
+        for (distance, scope) in self.scopes.iter_mut().rev().enumerate() {
+            if let Some(binding) = scope.get_mut(&*name.lexeme) {
+                binding.used = true;
+                depth.set(Some(distance));
+                return;
+            }
+        }
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn end_scope(&mut self) {
+        let Some(scope) = self.scopes.pop() else {
+            return;
+        };
+
+        let mut unused = scope
+            .into_values()
+            .filter(|binding| !binding.used)
+            .collect::<Vec<_>>();
+        unused.sort_by_key(|binding| binding.token.span.start);
+
+        self.warnings.extend(
+            unused
+                .into_iter()
+                .map(|binding| ResolveWarning::UnusedLocal {
+                    name: binding.token.lexeme.to_string(),
+                    span: binding.token.span,
+                }),
+        );
+    }
+
+    fn declare(&mut self, name: &Token<'a>) {
+        let Some(scope) = self.scopes.last_mut() else {
+            return;
+        };
+
+        if scope.contains_key(&*name.lexeme) {
+            self.errors.push(ResolveError::DuplicateLocal {
+                name: name.lexeme.to_string(),
+                span: name.span,
+            });
+        }
+
+        scope.insert(
+            name.lexeme.clone(),
+            Binding {
+                token: name.clone(),
+                defined: false,
+                used: false,
+            },
+        );
+    }
+
+    fn define(&mut self, name: &Token<'a>) {
+        if let Some(binding) = self
+            .scopes
+            .last_mut()
+            .and_then(|scope| scope.get_mut(&*name.lexeme))
+        {
+            binding.defined = true;
+        }
+    }
+
+    fn mark_used(&mut self, name: &str) {
+        if let Some(binding) = self.scopes.last_mut().and_then(|scope| scope.get_mut(name)) {
+            binding.used = true;
+        }
+    }
+
+    /// Declares `this`/`super`, which are always considered used
+    fn define_implicit(&mut self, name: &'a str, class_name: &Token<'a>) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(
+                Cow::Borrowed(name),
+                Binding {
+                    token: class_name.clone(),
+                    defined: true,
+                    used: true,
+                },
+            );
+        }
+    }
+}
@@ -1,8 +1,9 @@
-use std::{collections::HashMap, iter::Peekable, str::CharIndices};
+use std::{borrow::Cow, collections::HashMap, iter::Peekable, str::CharIndices};
 use thiserror::Error;
-use yansi::Paint;
+use unicode_normalization::{is_nfc_quick, IsNormalized, UnicodeNormalization};
+use unicode_xid::UnicodeXID;
 
-use crate::token::{Token, TokenType};
+use crate::token::{Span, Token, TokenType};
 // use color_eyre::Result;
 
 #[derive(Debug)]
@@ -11,16 +12,42 @@ pub struct Scanner<'a> {
     code: Peekable<CharIndices<'a>>,
     pub tokens: Vec<Token<'a>>,
     pub reserved: HashMap<&'a str, TokenType<'a>>,
+    /// Byte offset of the current lexeme's first character
     start: usize,
+    /// Byte offset just past the last character consumed
     current: usize,
     line: usize,
-    has_error: bool,
+    line_start: usize,
+    start_line: usize,
+    start_column: usize,
+    errors: Vec<ScannerError>,
+    /// Interpolations the scanner is inside of: braces opened within each one
+    /// and the span of its `${`
+    interpolations: Vec<(usize, Span)>,
+    /// Whether whitespace, comments and lexemes that failed to scan are kept
+    /// as tokens, so the token stream spells out the source exactly
+    lossless: bool,
 }
 
-#[derive(Debug, Error)]
+/// Lexical errors, spanning the offending lexeme
+#[derive(Debug, Clone, Error, PartialEq)]
 pub enum ScannerError {
-    #[error("Invalid char")]
-    InvalidCharacter(usize, usize),
+    #[error("Invalid character '{character}'")]
+    InvalidCharacter { character: char, span: Span },
+    #[error("Unterminated string")]
+    UnterminatedString { span: Span },
+    #[error("Invalid escape sequence '{sequence}'")]
+    InvalidEscape { sequence: String, span: Span },
+    #[error("Unterminated string interpolation")]
+    UnterminatedInterpolation { span: Span },
+    #[error("Invalid unicode escape '{sequence}'")]
+    InvalidUnicodeEscape { sequence: String, span: Span },
+    #[error("Unterminated block comment")]
+    UnterminatedComment { span: Span },
+    #[error("Malformed number '{lexeme}'")]
+    MalformedNumber { lexeme: String, span: Span },
+    #[error("Integer '{lexeme}' doesn't fit in 64 bits")]
+    IntegerOutOfRange { lexeme: String, span: Span },
 }
 
 impl<'a> Scanner<'a> {
@@ -34,6 +61,7 @@ impl<'a> Scanner<'a> {
         reserved.insert("while", TokenType::While);
         reserved.insert("Nil", TokenType::Nil);
         reserved.insert("class", TokenType::Class);
+        reserved.insert("fun", TokenType::Fun);
         reserved.insert("return", TokenType::Return);
         reserved.insert("super", TokenType::Super);
         reserved.insert("var", TokenType::Var);
@@ -43,238 +71,537 @@ impl<'a> Scanner<'a> {
         reserved.insert("false", TokenType::False);
 
         Scanner {
-            source_code: source_code.trim(),
+            source_code,
             code: source_code.char_indices().peekable(),
             tokens: vec![],
             reserved,
             start: 0,
             current: 0,
             line: 1,
-            has_error: false,
+            line_start: 0,
+            start_line: 1,
+            start_column: 1,
+            errors: vec![],
+            interpolations: vec![],
+            lossless: false,
         }
     }
 
-    pub fn scan_tokens(&mut self) -> Result<(), ScannerError> {
+    /// Switches to lossless mode: whitespace and comments are kept as
+    /// [`TokenType::Whitespace`] and [`TokenType::Comment`]. Concatenating
+    /// the lexemes then gives back the source, which is what the CST is
+    /// built from, so identifiers also keep their lexeme as it was typed.
+    pub fn lossless(mut self) -> Self {
+        self.lossless = true;
+        self
+    }
+
+    /// Scans the whole source into `tokens`. Scanning carries on past lexical
+    /// errors, leaving a [`TokenType::Error`] in place of each lexeme that
+    /// failed, so the token stream is usable even when errors are returned.
+    pub fn scan_tokens(&mut self) -> Result<(), Vec<ScannerError>> {
         while let Some((idx, c)) = self.code.next() {
             self.start = idx;
-            self.current = idx;
-            self.scan_token(c)?
+            self.current = idx + c.len_utf8();
+            self.start_line = self.line;
+            self.start_column = self.column();
+            if let Err(err) = self.scan_token(c) {
+                self.errors.push(err);
+                let _ = self.add_token(TokenType::Error);
+            }
+        }
+
+        for (_, span) in std::mem::take(&mut self.interpolations) {
+            self.errors
+                .push(ScannerError::UnterminatedInterpolation { span });
         }
 
-        self.start = self.current;
+        self.start = self.source_code.len();
+        let column = self.column();
         self.tokens.push(Token {
             token_type: TokenType::Eof,
-            lexeme: "",
-            line: self.line,
+            lexeme: Cow::Borrowed(""),
+            span: Span::new(self.start, self.start, self.line, column),
         });
 
-        Ok(())
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(std::mem::take(&mut self.errors))
+        }
+    }
+
+    /// Column of the current lexeme's first character, counted from 1
+    fn column(&self) -> usize {
+        self.column_of(self.start)
     }
 
-    // fn is_at_end(&self) -> bool {
-    //     self.current > self.source_code.chars().count() - 1
-    // }
+    /// Column of the byte `offset` on the current line, counted from 1
+    fn column_of(&self, offset: usize) -> usize {
+        self.source_code
+            .get(self.line_start..offset)
+            .map_or(0, |prefix| prefix.chars().count())
+            + 1
+    }
 
-    fn print_error(&self, start_index: usize, line_number: usize) {
-        let err = Paint::red(format!(
-            "Invalid character at char index {} and line number {}",
-            start_index, line_number
-        ));
-        println!("{}", err)
+    /// Byte offset of the next character to be consumed
+    fn offset(&mut self) -> usize {
+        self.code
+            .peek()
+            .map_or(self.source_code.len(), |(idx, _)| *idx)
+    }
+
+    /// Span from the start of the current lexeme up to the byte offset `end`
+    fn span_to(&self, end: usize) -> Span {
+        Span::new(self.start, end, self.start_line, self.start_column)
     }
 
     fn scan_token(&mut self, c: char) -> Result<(), ScannerError> {
         match c {
-            ')' => Ok(self.add_token(TokenType::RightParen)),
-            '(' => Ok(self.add_token(TokenType::LeftParen)),
-            '{' => Ok(self.add_token(TokenType::LeftBrace)),
-            '}' => Ok(self.add_token(TokenType::RightBrace)),
-            ';' => Ok(self.add_token(TokenType::SemiColon)),
-            '.' => Ok(self.add_token(TokenType::Dot)),
-            '*' => Ok(self.add_token(TokenType::Star)),
-            '+' => Ok(self.add_token(TokenType::Plus)),
-            '-' => Ok(self.add_token(TokenType::Minus)),
-            ',' => Ok(self.add_token(TokenType::Comma)),
+            ')' => self.add_token(TokenType::RightParen),
+            '(' => self.add_token(TokenType::LeftParen),
+            '{' => {
+                if let Some((braces, _)) = self.interpolations.last_mut() {
+                    *braces += 1;
+                }
+                self.add_token(TokenType::LeftBrace)
+            }
+            '}' => match self.interpolations.last_mut() {
+                // closes the interpolation, the string carries on
+                Some((0, _)) => {
+                    self.interpolations.pop();
+                    self.tokenize_string(true)
+                }
+                Some((braces, _)) => {
+                    *braces -= 1;
+                    self.add_token(TokenType::RightBrace)
+                }
+                None => self.add_token(TokenType::RightBrace),
+            },
+            ';' => self.add_token(TokenType::SemiColon),
+            '.' => self.add_token(TokenType::Dot),
+            '*' => self.add_token(TokenType::Star),
+            '+' => self.add_token(TokenType::Plus),
+            '-' => self.add_token(TokenType::Minus),
+            ',' => self.add_token(TokenType::Comma),
             '!' => {
                 if let Some((_, '=')) = self.code.peek() {
                     self.advance();
-                    Ok(self.add_token(TokenType::NotEqual))
+                    self.add_token(TokenType::NotEqual)
                 } else {
-                    Ok(self.add_token(TokenType::Not))
+                    self.add_token(TokenType::Not)
                 }
             }
-            '?' => Ok(self.add_token(TokenType::Question)),
-            ':' => Ok(self.add_token(TokenType::Colon)),
+            '?' => self.add_token(TokenType::Question),
+            ':' => self.add_token(TokenType::Colon),
             '>' => {
                 if let Some((_, '=')) = self.code.peek() {
                     self.advance();
-                    Ok(self.add_token(TokenType::GreaterEqual))
+                    self.add_token(TokenType::GreaterEqual)
                 } else {
-                    Ok(self.add_token(TokenType::Greater))
+                    self.add_token(TokenType::Greater)
                 }
             }
             '<' => {
                 if let Some((_, '=')) = self.code.peek() {
                     self.advance();
-                    Ok(self.add_token(TokenType::LessEqual))
+                    self.add_token(TokenType::LessEqual)
                 } else {
-                    Ok(self.add_token(TokenType::Less))
+                    self.add_token(TokenType::Less)
                 }
             }
             '/' => {
                 if let Some((_, '/')) = self.code.peek() {
-                    println!("Ignoring comments at line [{}]", self.line);
-                    while let Some((_, val)) = self.code.next() {
-                        if val == '\n' {
-                            self.line += 1;
-                            break;
-                        };
-                    }
-                    Ok(())
-                } else if let Some((_, '*')) = self.code.peek() {
-                    println!("Ignoring comments at line: [{}]", self.line);
-                    // consume the "*"
-                    self.advance();
-                    /* Hllosdlfkjsldkfjsldfkjl*k */
+                    // the newline itself is left to be scanned as a token
                     while let Some((_, val)) = self.code.peek() {
-                        if *val == '*' {
-                            self.advance();
-                            if let Some((_, '/')) = self.code.peek() {
-                                self.advance();
-                                return Ok(());
-                            }
-                        } else if *val == '\n' {
-                            self.line += 1;
-                            self.advance();
-                        } else {
-                            self.advance();
+                        if *val == '\n' {
+                            break;
                         }
+                        self.advance();
                     }
 
-                    if let None = self.code.peek() {
-                        println!("Unterminated comment at line: [{}]", self.line)
+                    // `///` starts a doc comment, but `////` is a plain one
+                    let comment = &self.source_code[self.start..self.current];
+                    if comment.starts_with("///") && !comment.starts_with("////") {
+                        return self.add_token(TokenType::DocComment);
                     }
-                    Ok(())
+                    self.add_trivia(TokenType::Comment)
+                } else if let Some((_, '*')) = self.code.peek() {
+                    self.block_comment()?;
+                    self.add_trivia(TokenType::Comment)
                 } else {
-                    Ok(self.add_token(TokenType::Slash))
+                    self.add_token(TokenType::Slash)
                 }
             }
             '=' => {
                 if let Some((_, '=')) = self.code.peek() {
                     self.advance();
-                    Ok(self.add_token(TokenType::EqualEqual))
+                    self.add_token(TokenType::EqualEqual)
                 } else {
-                    Ok(self.add_token(TokenType::Equal))
+                    self.add_token(TokenType::Equal)
                 }
             }
-            '"' => self.tokenize_string(),
-            num if num.is_numeric() => self.tokenize_number(),
-            c if (c.is_alphabetic() || c == '_') => self.tokenize_identifier(),
-            ' ' => Ok(()),
+            '"' => self.tokenize_string(false),
+            num if num.is_ascii_digit() => self.tokenize_number(num),
+            c if c == '_' || c.is_xid_start() => self.tokenize_identifier(),
+            ' ' | '\r' | '\t' => {
+                while let Some((_, ' ' | '\r' | '\t')) = self.code.peek() {
+                    self.advance();
+                }
+                self.add_trivia(TokenType::Whitespace)
+            }
             '\n' => {
                 self.line += 1;
-                Ok(self.add_token(TokenType::NewLine))
-            }
-            '\r' => Ok(()),
-            '\t' => Ok(()),
-            _ => {
-                self.has_error = true;
-                Ok(self.print_error(self.start, self.line))
+                self.line_start = self.start + 1;
+                self.add_token(TokenType::NewLine)
             }
+            character => Err(ScannerError::InvalidCharacter {
+                character,
+                span: self.span_to(self.start + character.len_utf8()),
+            }),
         }
     }
 
-    fn add_token(&mut self, token_type: TokenType<'a>) {
-        let lexeme = self
-            .source_code
-            .get(self.start..self.current + 1)
-            .unwrap_or("\n");
+    /// Skips a block comment whose `/` was just consumed. Block comments
+    /// nest, so code that already contains one can be commented out.
+    fn block_comment(&mut self) -> Result<(), ScannerError> {
+        // consume the "*"
+        self.advance();
+        let mut depth = 1;
+        while let Some(&(_, val)) = self.code.peek() {
+            self.advance();
+            match (val, self.code.peek()) {
+                ('*', Some((_, '/'))) => {
+                    self.advance();
+                    depth -= 1;
+                    if depth == 0 {
+                        return Ok(());
+                    }
+                }
+                ('/', Some((_, '*'))) => {
+                    self.advance();
+                    depth += 1;
+                }
+                _ => {}
+            }
+        }
 
-        let token_type = match token_type {
-            TokenType::String(_) => TokenType::String(lexeme),
-            TokenType::Number(_) => TokenType::Number(lexeme.parse::<f32>().unwrap()),
-            _ => token_type,
-        };
+        Err(ScannerError::UnterminatedComment {
+            span: self.span_to(self.start + "/*".len()),
+        })
+    }
+
+    fn add_token(&mut self, token_type: TokenType<'a>) -> Result<(), ScannerError> {
+        let lexeme = &self.source_code[self.start..self.current];
 
         self.tokens.push(Token {
             token_type,
-            lexeme,
-            line: self.line,
+            lexeme: Cow::Borrowed(lexeme),
+            span: self.span_to(self.start + lexeme.len()),
+        });
+        Ok(())
+    }
+
+    /// Adds a token that only lossless mode keeps
+    fn add_trivia(&mut self, token_type: TokenType<'a>) -> Result<(), ScannerError> {
+        if self.lossless {
+            self.add_token(token_type)?;
+        }
+        Ok(())
+    }
+
+    /// Scans a string literal, decoding its escapes. The contents are only
+    /// copied once an escape makes them differ from the source. A `${` ends
+    /// the token early, scanning resumes here after the matching `}` with
+    /// `resumed` set, so the pieces that carry a string on are told apart
+    /// from string literals inside the interpolated expression.
+    fn tokenize_string(&mut self, resumed: bool) -> Result<(), ScannerError> {
+        let contents_start = self.start + 1;
+        let mut decoded: Option<String> = None;
+        while let Some(&(idx, val)) = self.code.peek() {
+            match val {
+                '"' => {
+                    self.advance();
+                    let contents = match decoded {
+                        Some(decoded) => Cow::Owned(decoded),
+                        None => Cow::Borrowed(&self.source_code[contents_start..idx]),
+                    };
+                    return self.add_token(if resumed {
+                        TokenType::InterpolationEnd(contents)
+                    } else {
+                        TokenType::String(contents)
+                    });
+                }
+                '$' => {
+                    self.advance();
+                    if let Some((_, '{')) = self.code.peek() {
+                        self.advance();
+                        let contents = match decoded {
+                            Some(decoded) => Cow::Owned(decoded),
+                            None => Cow::Borrowed(&self.source_code[contents_start..idx]),
+                        };
+                        let span = Span::new(idx, idx + "${".len(), self.line, self.column_of(idx));
+                        self.interpolations.push((0, span));
+                        return self.add_token(if resumed {
+                            TokenType::InterpolationPart(contents)
+                        } else {
+                            TokenType::Interpolation(contents)
+                        });
+                    }
+                    if let Some(decoded) = &mut decoded {
+                        decoded.push('$');
+                    }
+                }
+                '\\' => {
+                    let decoded = decoded
+                        .get_or_insert_with(|| self.source_code[contents_start..idx].to_string());
+                    // invalid escapes are reported but the string is still
+                    // produced, so the parser doesn't trip over it
+                    match self.escape(idx) {
+                        Ok(Some(character)) => decoded.push(character),
+                        Ok(None) => break,
+                        Err(err) => self.errors.push(err),
+                    }
+                }
+                _ => {
+                    if let Some(decoded) = &mut decoded {
+                        decoded.push(val);
+                    }
+                    self.advance();
+                }
+            }
+        }
+
+        Err(ScannerError::UnterminatedString {
+            span: self.span_to(self.source_code.len()),
         })
     }
 
-    fn tokenize_string(&mut self) -> Result<(), ScannerError> {
-        while let Some((_, val)) = self.code.peek() {
-            if *val == '\n' {
-                self.advance();
-                self.line += 1;
-            } else if *val == '"' {
-                self.advance();
-                self.add_token(TokenType::String(""));
-                return Ok(());
-            } else {
-                self.advance()
+    /// Decodes the escape sequence starting with the backslash at byte
+    /// `start`, or returns `None` when the source ends right after it
+    fn escape(&mut self, start: usize) -> Result<Option<char>, ScannerError> {
+        let line = self.line;
+        let column = self.column_of(start);
+        // the backslash
+        self.advance();
+        let Some(&(_, escaped)) = self.code.peek() else {
+            return Ok(None);
+        };
+        self.advance();
+
+        let character = match escaped {
+            'n' => Some('\n'),
+            't' => Some('\t'),
+            '"' => Some('"'),
+            '\\' => Some('\\'),
+            '$' => Some('$'),
+            'u' => match self.unicode_escape() {
+                Some(character) => Some(character),
+                None => {
+                    let end = self.offset();
+                    return Err(ScannerError::InvalidUnicodeEscape {
+                        sequence: self.source_code[start..end].to_string(),
+                        span: Span::new(start, end, line, column),
+                    });
+                }
+            },
+            _ => None,
+        };
+
+        character.map(Some).ok_or_else(|| {
+            let end = start + 1 + escaped.len_utf8();
+            ScannerError::InvalidEscape {
+                sequence: self.source_code[start..end].to_string(),
+                span: Span::new(start, end, line, column),
             }
+        })
+    }
+
+    /// Decodes the `{...}` part of a `\u{...}` escape: one to six hex digits
+    /// naming a unicode scalar value
+    fn unicode_escape(&mut self) -> Option<char> {
+        if !matches!(self.code.peek(), Some((_, '{'))) {
+            return None;
         }
-        if let None = self.code.peek() {
-            println!(
-                "{}",
-                Paint::red(format!(
-                    "Unterminated string at index: {} and line :[{}]",
-                    &self.current, &self.line
-                ))
-            );
+        self.advance();
+
+        let mut digits = String::new();
+        while let Some(&(_, digit)) = self.code.peek() {
+            if !digit.is_ascii_hexdigit() {
+                break;
+            }
+            digits.push(digit);
+            self.advance();
         }
-        Ok(())
-    }
 
-    fn tokenize_number(&mut self) -> Result<(), ScannerError> {
-        while self.code.peek().is_some() && self.code.peek().unwrap().1.is_numeric() {
-            self.advance()
+        if !matches!(self.code.peek(), Some((_, '}'))) {
+            return None;
         }
+        self.advance();
 
-        if self.code.peek().is_some() && self.code.peek().unwrap().1 == '.' {
-            self.advance()
+        if digits.is_empty() || digits.len() > 6 {
+            return None;
         }
+        u32::from_str_radix(&digits, 16)
+            .ok()
+            .and_then(char::from_u32)
+    }
 
-        while self.code.peek().is_some() && self.code.peek().unwrap().1.is_numeric() {
-            self.advance()
+    /// Scans a number literal starting with the digit `first`. Integers can
+    /// be written in hex, binary or octal with `0x`, `0b` or `0o`, floats
+    /// need digits after their `.` and may have an exponent. `_` separates
+    /// digits anywhere between two of them.
+    fn tokenize_number(&mut self, first: char) -> Result<(), ScannerError> {
+        let radix = match (first, self.code.peek()) {
+            ('0', Some((_, 'x' | 'X'))) => Some(16),
+            ('0', Some((_, 'b' | 'B'))) => Some(2),
+            ('0', Some((_, 'o' | 'O'))) => Some(8),
+            _ => None,
+        };
+
+        let mut float = false;
+        if radix.is_some() {
+            self.advance();
+        } else {
+            self.skip_digits();
+            if let Some((_, '.')) = self.code.peek() {
+                float = true;
+                self.advance();
+                self.skip_digits();
+            }
+            if let Some((_, 'e' | 'E')) = self.code.peek() {
+                float = true;
+                self.advance();
+                if let Some((_, '+' | '-')) = self.code.peek() {
+                    self.advance();
+                }
+                self.skip_digits();
+            }
+        }
+        // letters glued to the literal belong to it, so `12ab` or `0b102`
+        // are reported whole rather than split into several tokens
+        while let Some((_, val)) = self.code.peek() {
+            if !(val.is_alphanumeric() || *val == '_') {
+                break;
+            }
+            self.advance();
         }
 
-        self.add_token(TokenType::Number(0.0));
+        let lexeme = &self.source_code[self.start..self.current];
+        let malformed = || ScannerError::MalformedNumber {
+            lexeme: lexeme.to_string(),
+            span: self.span_to(self.start + lexeme.len()),
+        };
 
-        Ok(())
+        let token_type = match radix {
+            Some(radix) => {
+                let digits = &lexeme[2..];
+                if !separated_digits(digits, |digit| digit.is_digit(radix)) {
+                    return Err(malformed());
+                }
+                match i64::from_str_radix(&digits.replace('_', ""), radix) {
+                    Ok(val) => TokenType::Integer(val),
+                    Err(_) => return Err(self.integer_out_of_range(lexeme)),
+                }
+            }
+            None if float => {
+                // every group of digits has to be well formed, which also
+                // rules out a dangling `1.` or `1e`
+                let (mantissa, exponent) = match lexeme.find(['e', 'E']) {
+                    Some(index) => (&lexeme[..index], Some(&lexeme[index + 1..])),
+                    None => (lexeme, None),
+                };
+                let mut groups = mantissa
+                    .split('.')
+                    .chain(exponent.map(|exponent| exponent.trim_start_matches(['+', '-'])));
+                if !groups.all(|group| separated_digits(group, |digit| digit.is_ascii_digit())) {
+                    return Err(malformed());
+                }
+                match lexeme.replace('_', "").parse::<f64>() {
+                    Ok(val) => TokenType::Number(val),
+                    Err(_) => return Err(malformed()),
+                }
+            }
+            None => {
+                if !separated_digits(lexeme, |digit| digit.is_ascii_digit()) {
+                    return Err(malformed());
+                }
+                match lexeme.replace('_', "").parse::<i64>() {
+                    Ok(val) => TokenType::Integer(val),
+                    Err(_) => return Err(self.integer_out_of_range(lexeme)),
+                }
+            }
+        };
+        self.add_token(token_type)
     }
 
-    fn tokenize_identifier(&mut self) -> Result<(), ScannerError> {
-        self.advance();
+    /// Consumes decimal digits and separators
+    fn skip_digits(&mut self) {
+        while let Some((_, val)) = self.code.peek() {
+            if !(val.is_ascii_digit() || *val == '_') {
+                break;
+            }
+            self.advance();
+        }
+    }
+
+    fn integer_out_of_range(&self, lexeme: &str) -> ScannerError {
+        ScannerError::IntegerOutOfRange {
+            lexeme: lexeme.to_string(),
+            span: self.span_to(self.start + lexeme.len()),
+        }
+    }
 
+    /// Identifiers follow UAX #31: an XID_Start character or `_`, then any
+    /// XID_Continue characters
+    fn tokenize_identifier(&mut self) -> Result<(), ScannerError> {
         while let Some((_, val)) = self.code.peek() {
-            if !val.is_alphanumeric() {
+            if !val.is_xid_continue() {
                 break;
             }
             self.advance()
         }
 
-        let lexeme = self.source_code.get(self.start..self.current + 1).unwrap();
+        let lexeme = &self.source_code[self.start..self.current];
 
         if let Some(token_type) = self.reserved.get(lexeme) {
-            self.add_token(token_type.clone())
-        } else {
-            self.add_token(TokenType::Identifier)
+            return self.add_token(token_type.clone());
+        }
+        self.add_token(TokenType::Identifier)?;
+        if !self.lossless {
+            if let Some(token) = self.tokens.last_mut() {
+                token.lexeme = normalize(lexeme);
+            }
         }
-
         Ok(())
     }
 
     fn advance(&mut self) {
-        let _ = self.code.next();
-        self.current += 1;
+        if let Some((idx, c)) = self.code.next() {
+            if c == '\n' {
+                self.line += 1;
+                self.line_start = idx + 1;
+            }
+            self.current = idx + c.len_utf8();
+        }
     }
+}
 
-    pub fn print_tokens(&self) {
-        for token in &self.tokens {
-            println!("{:?}", token)
-        }
+/// Puts an identifier in Unicode normalization form C, so identifiers that
+/// look the same are the same however they were typed. String literals are
+/// left alone, their contents are the program's data.
+fn normalize(source: &str) -> Cow<'_, str> {
+    match is_nfc_quick(source.chars()) {
+        IsNormalized::Yes => Cow::Borrowed(source),
+        _ => Cow::Owned(source.nfc().collect()),
     }
 }
+
+/// Whether `digits` is a non-empty run of digits with single `_` separators
+/// only between two of them
+fn separated_digits(digits: &str, is_digit: impl Fn(char) -> bool) -> bool {
+    !digits.is_empty()
+        && digits
+            .split('_')
+            .all(|group| !group.is_empty() && group.chars().all(&is_digit))
+}
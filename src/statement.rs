@@ -0,0 +1,192 @@
+use std::{fmt::Display, rc::Rc};
+
+use crate::{
+    expression::Expression,
+    token::{Span, Token},
+};
+
+#[derive(Debug, PartialEq)]
+pub enum Stmt<'a> {
+    Expression(Expression<'a>),
+    Print(Token<'a>, Expression<'a>),
+    /// A `var` declaration and the `///` comments written right before it
+    Var(Token<'a>, Option<Expression<'a>>, Vec<Token<'a>>),
+    Block(Vec<Stmt<'a>>, Span),
+    If(
+        Token<'a>,
+        Expression<'a>,
+        Box<Stmt<'a>>,
+        Option<Box<Stmt<'a>>>,
+    ),
+    While(Token<'a>, Expression<'a>, Box<Stmt<'a>>),
+    Function(Rc<FunctionDecl<'a>>),
+    Class(ClassDecl<'a>),
+    Return(Token<'a>, Option<Expression<'a>>),
+}
+
+/// A `fun` declaration, shared between the AST and the functions created from it
+#[derive(Debug, PartialEq)]
+pub struct FunctionDecl<'a> {
+    /// `///` comments written right before the declaration
+    pub docs: Vec<Token<'a>>,
+    pub name: Token<'a>,
+    pub params: Vec<Token<'a>>,
+    pub body: Vec<Stmt<'a>>,
+    pub span: Span,
+}
+
+impl<'a> FunctionDecl<'a> {
+    /// A copy owning all its text, so it can outlive the source
+    pub fn to_static(&self) -> FunctionDecl<'static> {
+        FunctionDecl {
+            docs: static_tokens(&self.docs),
+            name: self.name.to_static(),
+            params: static_tokens(&self.params),
+            body: static_statements(&self.body),
+            span: self.span,
+        }
+    }
+}
+
+/// A `class` declaration with an optional `< Superclass` clause
+#[derive(Debug, PartialEq)]
+pub struct ClassDecl<'a> {
+    /// `///` comments written right before the declaration
+    pub docs: Vec<Token<'a>>,
+    pub name: Token<'a>,
+    pub superclass: Option<Expression<'a>>,
+    pub methods: Vec<Rc<FunctionDecl<'a>>>,
+    pub span: Span,
+}
+
+/// Copies of `statements` owning all their text, see [`Stmt::to_static`]
+pub fn static_statements(statements: &[Stmt]) -> Vec<Stmt<'static>> {
+    statements.iter().map(Stmt::to_static).collect()
+}
+
+fn static_tokens(tokens: &[Token]) -> Vec<Token<'static>> {
+    tokens.iter().map(Token::to_static).collect()
+}
+
+/// Text of a run of doc comments, without the `///` markers and the space
+/// that usually follows them
+pub fn doc_text(docs: &[Token]) -> String {
+    docs.iter()
+        .map(|doc| {
+            let text = doc.lexeme.trim_start_matches("///");
+            text.strip_prefix(' ').unwrap_or(text).trim_end()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+impl<'a> Stmt<'a> {
+    /// A copy owning all its text, so it can outlive the source
+    pub fn to_static(&self) -> Stmt<'static> {
+        let boxed = |statement: &Stmt<'a>| Box::new(statement.to_static());
+        match self {
+            Self::Expression(exp) => Stmt::Expression(exp.to_static()),
+            Self::Print(keyword, exp) => Stmt::Print(keyword.to_static(), exp.to_static()),
+            Self::Var(name, initializer, docs) => Stmt::Var(
+                name.to_static(),
+                initializer.as_ref().map(Expression::to_static),
+                static_tokens(docs),
+            ),
+            Self::Block(statements, span) => Stmt::Block(static_statements(statements), *span),
+            Self::If(keyword, condition, then_branch, else_branch) => Stmt::If(
+                keyword.to_static(),
+                condition.to_static(),
+                boxed(then_branch),
+                else_branch.as_deref().map(boxed),
+            ),
+            Self::While(keyword, condition, body) => {
+                Stmt::While(keyword.to_static(), condition.to_static(), boxed(body))
+            }
+            Self::Function(declaration) => Stmt::Function(Rc::new(declaration.to_static())),
+            Self::Class(declaration) => Stmt::Class(ClassDecl {
+                docs: static_tokens(&declaration.docs),
+                name: declaration.name.to_static(),
+                superclass: declaration.superclass.as_ref().map(Expression::to_static),
+                methods: declaration
+                    .methods
+                    .iter()
+                    .map(|method| Rc::new(method.to_static()))
+                    .collect(),
+                span: declaration.span,
+            }),
+            Self::Return(keyword, value) => Stmt::Return(
+                keyword.to_static(),
+                value.as_ref().map(Expression::to_static),
+            ),
+        }
+    }
+
+    /// Source range covered by the statement, leaving out a trailing `;`
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Expression(exp) => exp.span(),
+            Self::Print(keyword, exp) => keyword.span.to(exp.span()),
+            Self::Var(name, Some(initializer), _) => name.span.to(initializer.span()),
+            Self::Var(name, None, _) => name.span,
+            Self::Block(_, span) => *span,
+            Self::If(keyword, _, _, Some(else_branch)) => keyword.span.to(else_branch.span()),
+            Self::If(keyword, _, then_branch, None) => keyword.span.to(then_branch.span()),
+            Self::While(keyword, _, body) => keyword.span.to(body.span()),
+            Self::Function(declaration) => declaration.span,
+            Self::Class(declaration) => declaration.span,
+            Self::Return(keyword, Some(value)) => keyword.span.to(value.span()),
+            Self::Return(keyword, None) => keyword.span,
+        }
+    }
+}
+
+impl<'a> Display for Stmt<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Expression(exp) => write!(f, "{};", exp),
+            Self::Print(_, exp) => write!(f, "(print {});", exp),
+            Self::Var(name, Some(initializer), _) => {
+                write!(f, "(var {} = {});", name.lexeme, initializer)
+            }
+            Self::Var(name, None, _) => write!(f, "(var {});", name.lexeme),
+            Self::Block(statements, _) => {
+                write!(f, "{{")?;
+                for statement in statements {
+                    write!(f, " {}", statement)?;
+                }
+                write!(f, " }}")
+            }
+            Self::If(_, condition, then_branch, Some(else_branch)) => {
+                write!(f, "(if {} {} else {})", condition, then_branch, else_branch)
+            }
+            Self::If(_, condition, then_branch, None) => {
+                write!(f, "(if {} {})", condition, then_branch)
+            }
+            Self::While(_, condition, body) => write!(f, "(while {} {})", condition, body),
+            Self::Function(declaration) => {
+                let params = declaration
+                    .params
+                    .iter()
+                    .map(|param| &*param.lexeme)
+                    .collect::<Vec<_>>();
+                write!(f, "(fun {}({})", declaration.name.lexeme, params.join(", "))?;
+                for statement in &declaration.body {
+                    write!(f, " {}", statement)?;
+                }
+                write!(f, ")")
+            }
+            Self::Class(declaration) => {
+                write!(f, "(class {}", declaration.name.lexeme)?;
+                if let Some(superclass) = &declaration.superclass {
+                    write!(f, " < {}", superclass)?;
+                }
+                for method in &declaration.methods {
+                    write!(f, " {}", Stmt::Function(Rc::clone(method)))?;
+                }
+                write!(f, ")")
+            }
+            Self::Return(_, Some(value)) => write!(f, "(return {});", value),
+            Self::Return(_, None) => write!(f, "(return);"),
+        }
+    }
+}
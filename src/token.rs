@@ -1,9 +1,9 @@
-use std::fmt::Display;
+use std::{borrow::Cow, fmt::Display};
 
 use yansi::Paint;
 
 /// Token type enum
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum TokenType<'a> {
     //Single char tokens
     LeftParen,
@@ -20,6 +20,14 @@ pub enum TokenType<'a> {
     Question,
     Colon,
     NewLine,
+    /// A `///` comment, kept for the declaration that follows it
+    DocComment,
+    // Trivia, only produced by a lossless scanner
+    Whitespace,
+    Comment,
+    /// A lexeme that failed to scan, its error is reported separately. The
+    /// parser takes it for an operand.
+    Error,
     // One or two char tokens
     Not,
     NotEqual,
@@ -31,8 +39,20 @@ pub enum TokenType<'a> {
     LessEqual,
     // literals
     Identifier,
-    String(&'a str),
-    Number(f32),
+    /// Contents with the quotes removed and escapes decoded, borrowed from
+    /// the source when there was nothing to decode
+    String(Cow<'a, str>),
+    /// Part of an interpolated string up to a `${`, the tokens of the
+    /// interpolated expression follow. The string carries on after the
+    /// matching `}` with [`TokenType::InterpolationPart`]s and ends with a
+    /// [`TokenType::InterpolationEnd`].
+    Interpolation(Cow<'a, str>),
+    /// Part of an interpolated string between a `}` and the next `${`
+    InterpolationPart(Cow<'a, str>),
+    /// Part of an interpolated string from the last `}` to the closing quote
+    InterpolationEnd(Cow<'a, str>),
+    Number(f64),
+    Integer(i64),
     //Keywords
     And,
     For,
@@ -40,6 +60,7 @@ pub enum TokenType<'a> {
     Else,
     While,
     Class,
+    Fun,
     Nil,
     Or,
     False,
@@ -52,20 +73,128 @@ pub enum TokenType<'a> {
     Eof,
 }
 
-/// Token struct
-#[derive(Debug, PartialEq, Copy, Clone)]
+impl<'a> TokenType<'a> {
+    /// A copy owning its contents, so it can outlive the source
+    pub fn to_static(&self) -> TokenType<'static> {
+        match self {
+            TokenType::LeftParen => TokenType::LeftParen,
+            TokenType::RightParen => TokenType::RightParen,
+            TokenType::LeftBrace => TokenType::LeftBrace,
+            TokenType::RightBrace => TokenType::RightBrace,
+            TokenType::Comma => TokenType::Comma,
+            TokenType::Dot => TokenType::Dot,
+            TokenType::SemiColon => TokenType::SemiColon,
+            TokenType::Minus => TokenType::Minus,
+            TokenType::Plus => TokenType::Plus,
+            TokenType::Slash => TokenType::Slash,
+            TokenType::Star => TokenType::Star,
+            TokenType::Question => TokenType::Question,
+            TokenType::Colon => TokenType::Colon,
+            TokenType::NewLine => TokenType::NewLine,
+            TokenType::DocComment => TokenType::DocComment,
+            TokenType::Whitespace => TokenType::Whitespace,
+            TokenType::Comment => TokenType::Comment,
+            TokenType::Error => TokenType::Error,
+            TokenType::Not => TokenType::Not,
+            TokenType::NotEqual => TokenType::NotEqual,
+            TokenType::Equal => TokenType::Equal,
+            TokenType::EqualEqual => TokenType::EqualEqual,
+            TokenType::Greater => TokenType::Greater,
+            TokenType::GreaterEqual => TokenType::GreaterEqual,
+            TokenType::Less => TokenType::Less,
+            TokenType::LessEqual => TokenType::LessEqual,
+            TokenType::Identifier => TokenType::Identifier,
+            TokenType::String(val) => TokenType::String(Cow::Owned(val.to_string())),
+            TokenType::Interpolation(val) => TokenType::Interpolation(Cow::Owned(val.to_string())),
+            TokenType::InterpolationPart(val) => {
+                TokenType::InterpolationPart(Cow::Owned(val.to_string()))
+            }
+            TokenType::InterpolationEnd(val) => {
+                TokenType::InterpolationEnd(Cow::Owned(val.to_string()))
+            }
+            TokenType::Number(val) => TokenType::Number(*val),
+            TokenType::Integer(val) => TokenType::Integer(*val),
+            TokenType::And => TokenType::And,
+            TokenType::For => TokenType::For,
+            TokenType::If => TokenType::If,
+            TokenType::Else => TokenType::Else,
+            TokenType::While => TokenType::While,
+            TokenType::Class => TokenType::Class,
+            TokenType::Fun => TokenType::Fun,
+            TokenType::Nil => TokenType::Nil,
+            TokenType::Or => TokenType::Or,
+            TokenType::False => TokenType::False,
+            TokenType::True => TokenType::True,
+            TokenType::Var => TokenType::Var,
+            TokenType::Print => TokenType::Print,
+            TokenType::Return => TokenType::Return,
+            TokenType::This => TokenType::This,
+            TokenType::Super => TokenType::Super,
+            TokenType::Eof => TokenType::Eof,
+        }
+    }
+}
+
+/// A range of source code. `start` and `end` are byte offsets with `end`
+/// exclusive, `line` and `column` locate `start` and are counted from 1
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize, line: usize, column: usize) -> Self {
+        Span {
+            start,
+            end,
+            line,
+            column,
+        }
+    }
+
+    /// Smallest span covering both `self` and `other`
+    pub fn to(self, other: Span) -> Span {
+        let (first, last) = if self.start <= other.start {
+            (self, other)
+        } else {
+            (other, self)
+        };
+
+        Span {
+            end: first.end.max(last.end),
+            ..first
+        }
+    }
+}
+
+/// Token struct. `lexeme` is the raw source text, quotes and escapes
+/// included for strings, except that identifiers are in Unicode
+/// normalization form C
+#[derive(Debug, PartialEq, Clone)]
 pub struct Token<'a> {
     pub token_type: TokenType<'a>,
-    pub lexeme: &'a str,
-    pub line: usize,
+    pub lexeme: Cow<'a, str>,
+    pub span: Span,
 }
 
 impl<'a> Token<'a> {
-    pub fn new(token_type: TokenType<'a>, lexeme: &'a str, line: usize) -> Self {
+    pub fn new(token_type: TokenType<'a>, lexeme: impl Into<Cow<'a, str>>, span: Span) -> Self {
         Token {
             token_type,
-            lexeme,
-            line,
+            lexeme: lexeme.into(),
+            span,
+        }
+    }
+
+    /// A copy owning its lexeme and contents, so it can outlive the source
+    pub fn to_static(&self) -> Token<'static> {
+        Token {
+            token_type: self.token_type.to_static(),
+            lexeme: Cow::Owned(self.lexeme.to_string()),
+            span: self.span,
         }
     }
 
@@ -80,7 +209,7 @@ impl<'a> Display for Token<'a> {
             Paint::yellow(&self.token_type),
             Paint::blue("literal"),
             Paint::green(&self.lexeme),
-            Paint::yellow(&self.line)
+            Paint::yellow(&self.span.line)
         )
     }
 }
@@ -88,8 +217,10 @@ impl<'a> Display for Token<'a> {
 impl<'a> Display for TokenType<'a> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            TokenType::String(val) => write!(f, "\"{}\"", Paint::green(val)),
+            // escaped again, so the literal reads the way it was written
+            TokenType::String(val) => write!(f, "{}", Paint::green(format!("{:?}", val))),
             TokenType::Number(val) => write!(f, "{}", Paint::yellow(val)),
+            TokenType::Integer(val) => write!(f, "{}", Paint::yellow(val)),
             TokenType::Plus => write!(f, "+"),
             TokenType::Minus => write!(f, "-"),
             TokenType::Star => write!(f, "*"),
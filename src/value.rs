@@ -0,0 +1,94 @@
+use std::{cell::RefCell, fmt::Display, rc::Rc};
+
+use crate::{
+    class::{Class, Instance},
+    function::Function,
+    native::{Native, NativeValue},
+    number::Number,
+};
+
+/// Runtime values produced by the interpreter
+#[derive(Debug, Clone)]
+pub enum Value<'a> {
+    Nil,
+    Bool(bool),
+    Number(Number),
+    String(String),
+    Function(Rc<Function<'a>>),
+    NativeFunction(Native),
+    Class(Rc<Class<'a>>),
+    Instance(Rc<RefCell<Instance<'a>>>),
+}
+
+impl<'a> Value<'a> {
+    /// `nil` and `false` are falsy, everything else is truthy
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Value::Nil | Value::Bool(false))
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Nil => "nil",
+            Value::Bool(_) => "bool",
+            Value::Number(_) => "number",
+            Value::String(_) => "string",
+            Value::Function(_) | Value::NativeFunction(_) => "function",
+            Value::Class(_) => "class",
+            Value::Instance(_) => "instance",
+        }
+    }
+}
+
+impl<'a> PartialEq for Value<'a> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Nil, Value::Nil) => true,
+            (Value::Bool(l), Value::Bool(r)) => l == r,
+            (Value::Number(l), Value::Number(r)) => l == r,
+            (Value::String(l), Value::String(r)) => l == r,
+            (Value::Function(l), Value::Function(r)) => Rc::ptr_eq(l, r),
+            (Value::NativeFunction(l), Value::NativeFunction(r)) => l == r,
+            (Value::Class(l), Value::Class(r)) => Rc::ptr_eq(l, r),
+            (Value::Instance(l), Value::Instance(r)) => Rc::ptr_eq(l, r),
+            _ => false,
+        }
+    }
+}
+
+impl<'a> From<&Value<'a>> for NativeValue {
+    fn from(value: &Value<'a>) -> Self {
+        match value {
+            Value::Nil => NativeValue::Nil,
+            Value::Bool(val) => NativeValue::Bool(*val),
+            Value::Number(val) => NativeValue::Number(*val),
+            Value::String(val) => NativeValue::String(val.clone()),
+            _ => NativeValue::Other,
+        }
+    }
+}
+
+impl<'a> From<NativeValue> for Value<'a> {
+    fn from(value: NativeValue) -> Self {
+        match value {
+            NativeValue::Bool(val) => Value::Bool(val),
+            NativeValue::Number(val) => Value::Number(val),
+            NativeValue::String(val) => Value::String(val),
+            NativeValue::Nil | NativeValue::Other => Value::Nil,
+        }
+    }
+}
+
+impl<'a> Display for Value<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Nil => write!(f, "nil"),
+            Value::Bool(val) => write!(f, "{}", val),
+            Value::Number(val) => write!(f, "{}", val),
+            Value::String(val) => write!(f, "{}", val),
+            Value::Function(function) => write!(f, "<fn {}>", function.declaration.name.lexeme),
+            Value::NativeFunction(native) => write!(f, "<native fn {}>", native.name),
+            Value::Class(class) => write!(f, "{}", class.name),
+            Value::Instance(instance) => write!(f, "{} instance", instance.borrow().class.name),
+        }
+    }
+}
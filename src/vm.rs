@@ -0,0 +1,691 @@
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+    rc::Rc,
+    sync::{atomic::AtomicBool, Arc},
+};
+
+use crate::{
+    chunk::{Constant, OpCode, Prototype},
+    interpreter::RuntimeError,
+    native::{Native, NativeValue},
+    number::{ArithmeticError, Number},
+    object::{
+        BoundMethod, Class, Closure, GcConfig, Heap, Instance, ObjRef, Object, Upvalue, Value,
+    },
+    token::Span,
+};
+
+/// Calls nested deeper than this are reported as a stack overflow
+const FRAMES_MAX: usize = 1024;
+
+/// A function invocation in progress
+#[derive(Debug)]
+struct CallFrame {
+    closure: ObjRef,
+    function: Rc<Prototype>,
+    ip: usize,
+    /// Stack index of slot 0, the callee or `this`
+    base: usize,
+}
+
+/// Stack based virtual machine running compiled kai programs
+pub struct Vm {
+    stack: Vec<Value>,
+    frames: Vec<CallFrame>,
+    globals: HashMap<Rc<str>, Value>,
+    heap: Heap,
+    /// Upvalues still pointing into the stack, so closures created in the
+    /// same scope share them
+    open_upvalues: Vec<ObjRef>,
+    output: Box<dyn Write>,
+    interrupt: Arc<AtomicBool>,
+}
+
+impl Default for Vm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Vm {
+    pub fn new() -> Self {
+        Self::with_output(Box::new(io::stdout()))
+    }
+
+    /// Creates a VM that writes `print` output to `output`
+    pub fn with_output(output: Box<dyn Write>) -> Self {
+        let globals = Native::all()
+            .into_iter()
+            .map(|native| (Rc::from(native.name), Value::Native(native)))
+            .collect();
+
+        Vm {
+            stack: vec![],
+            frames: vec![],
+            globals,
+            heap: Heap::new(),
+            open_upvalues: vec![],
+            output,
+            interrupt: Arc::default(),
+        }
+    }
+
+    /// Makes the VM stop with [`RuntimeError::Interrupted`] at the next
+    /// backward jump or call after `interrupt` is raised
+    pub fn with_interrupt(mut self, interrupt: Arc<AtomicBool>) -> Self {
+        self.interrupt = interrupt;
+        self
+    }
+
+    /// Names of the global variables, functions and classes
+    pub fn global_names(&self) -> Vec<String> {
+        self.globals.keys().map(|name| name.to_string()).collect()
+    }
+
+    /// Replaces the heap, so collections follow `config`
+    pub fn with_gc(mut self, config: GcConfig) -> Self {
+        self.heap = Heap::with_config(config);
+        self
+    }
+
+    /// Number of heap objects still alive
+    pub fn live_objects(&self) -> usize {
+        self.heap.live()
+    }
+
+    pub fn interpret(&mut self, script: Rc<Prototype>) -> Result<(), RuntimeError> {
+        let closure = self.alloc(Object::Closure(Closure {
+            function: Rc::clone(&script),
+            upvalues: vec![],
+        }));
+        self.stack.push(Value::Object(closure));
+        self.frames.push(CallFrame {
+            closure,
+            function: script,
+            ip: 0,
+            base: 0,
+        });
+
+        let result = self.run();
+        if result.is_err() {
+            self.stack.clear();
+            self.frames.clear();
+            self.open_upvalues.clear();
+        }
+        result
+    }
+
+    fn run(&mut self) -> Result<(), RuntimeError> {
+        loop {
+            let start = self.frame().ip;
+            let byte = self.read_byte();
+            let Some(op) = OpCode::from_byte(byte) else {
+                panic!("unknown opcode {} at offset {}", byte, start);
+            };
+
+            match op {
+                OpCode::Constant => {
+                    let value = match self.read_constant() {
+                        Constant::Integer(val) => Value::Number(Number::Integer(val)),
+                        Constant::Number(val) => Value::Number(Number::Float(val)),
+                        Constant::String(val) => Value::String(val),
+                        Constant::Function(_) => Value::Nil,
+                    };
+                    self.stack.push(value);
+                }
+                OpCode::Nil => self.stack.push(Value::Nil),
+                OpCode::True => self.stack.push(Value::Bool(true)),
+                OpCode::False => self.stack.push(Value::Bool(false)),
+                OpCode::Pop => {
+                    self.pop();
+                }
+                OpCode::GetLocal => {
+                    let slot = self.frame().base + self.read_byte() as usize;
+                    self.stack.push(self.stack[slot].clone());
+                }
+                OpCode::SetLocal => {
+                    let slot = self.frame().base + self.read_byte() as usize;
+                    self.stack[slot] = self.peek(0).clone();
+                }
+                OpCode::GetGlobal => {
+                    let name = self.read_string();
+                    match self.globals.get(&name) {
+                        Some(value) => self.stack.push(value.clone()),
+                        None => return Err(undefined_variable(&name, self.span_at(start))),
+                    }
+                }
+                OpCode::DefineGlobal => {
+                    let name = self.read_string();
+                    let value = self.pop();
+                    self.globals.insert(name, value);
+                }
+                OpCode::SetGlobal => {
+                    let name = self.read_string();
+                    let value = self.peek(0).clone();
+                    match self.globals.get_mut(&name) {
+                        Some(global) => *global = value,
+                        None => return Err(undefined_variable(&name, self.span_at(start))),
+                    }
+                }
+                OpCode::GetUpvalue => {
+                    let upvalue = self.frame_upvalue();
+                    let value = match self.heap.upvalue_mut(upvalue) {
+                        Upvalue::Open(slot) => self.stack[*slot].clone(),
+                        Upvalue::Closed(value) => value.clone(),
+                    };
+                    self.stack.push(value);
+                }
+                OpCode::SetUpvalue => {
+                    let upvalue = self.frame_upvalue();
+                    let value = self.peek(0).clone();
+                    match self.heap.upvalue_mut(upvalue) {
+                        Upvalue::Open(slot) => {
+                            let slot = *slot;
+                            self.stack[slot] = value;
+                        }
+                        Upvalue::Closed(closed) => *closed = value,
+                    }
+                }
+                OpCode::GetProperty => {
+                    let name = self.read_string();
+                    let instance = match self.peek(0) {
+                        Value::Object(reference) => *reference,
+                        _ => {
+                            return Err(RuntimeError::NotAnInstance {
+                                span: self.span_at(start),
+                            })
+                        }
+                    };
+                    let (class, field) = match self.heap.get(instance) {
+                        Object::Instance(instance) => {
+                            (instance.class, instance.fields.get(&name).cloned())
+                        }
+                        _ => {
+                            return Err(RuntimeError::NotAnInstance {
+                                span: self.span_at(start),
+                            })
+                        }
+                    };
+
+                    // fields shadow methods
+                    let value = match field {
+                        Some(value) => value,
+                        None => self.bind_method(class, &name, Value::Object(instance), start)?,
+                    };
+                    self.pop();
+                    self.stack.push(value);
+                }
+                OpCode::SetProperty => {
+                    let name = self.read_string();
+                    let value = self.pop();
+                    let instance = self.pop();
+                    match instance {
+                        Value::Object(reference) => match self.heap.get_mut(reference) {
+                            Object::Instance(instance) => {
+                                instance.fields.insert(name, value.clone());
+                            }
+                            _ => {
+                                return Err(RuntimeError::NotAnInstance {
+                                    span: self.span_at(start),
+                                })
+                            }
+                        },
+                        _ => {
+                            return Err(RuntimeError::NotAnInstance {
+                                span: self.span_at(start),
+                            })
+                        }
+                    }
+                    self.stack.push(value);
+                }
+                OpCode::GetSuper => {
+                    let name = self.read_string();
+                    let superclass = match self.pop() {
+                        Value::Object(reference) => reference,
+                        _ => {
+                            return Err(RuntimeError::SuperOutsideSubclass {
+                                span: self.span_at(start),
+                            })
+                        }
+                    };
+                    let receiver = self.pop();
+                    let method = self.bind_method(superclass, &name, receiver, start)?;
+                    self.stack.push(method);
+                }
+                OpCode::Equal => {
+                    let (left, right) = self.pop_pair();
+                    self.stack.push(Value::Bool(left == right));
+                }
+                OpCode::NotEqual => {
+                    let (left, right) = self.pop_pair();
+                    self.stack.push(Value::Bool(left != right));
+                }
+                OpCode::Greater => self.comparison(">", start, |l, r| l > r)?,
+                OpCode::GreaterEqual => self.comparison(">=", start, |l, r| l >= r)?,
+                OpCode::Less => self.comparison("<", start, |l, r| l < r)?,
+                OpCode::LessEqual => self.comparison("<=", start, |l, r| l <= r)?,
+                OpCode::Add => {
+                    let value = match self.pop_pair() {
+                        (Value::Number(l), Value::Number(r)) => match l.checked_add(r) {
+                            Ok(sum) => Value::Number(sum),
+                            Err(err) => {
+                                return Err(RuntimeError::arithmetic(err, "+", self.span_at(start)))
+                            }
+                        },
+                        (Value::String(l), Value::String(r)) => {
+                            Value::String(Rc::from(format!("{}{}", l, r)))
+                        }
+                        _ => {
+                            return Err(RuntimeError::InvalidAddition {
+                                span: self.span_at(start),
+                            })
+                        }
+                    };
+                    self.stack.push(value);
+                }
+                OpCode::Concat => {
+                    let (left, right) = self.pop_pair();
+                    let joined =
+                        format!("{}{}", self.heap.display(&left), self.heap.display(&right));
+                    self.stack.push(Value::String(Rc::from(joined)));
+                }
+                OpCode::Subtract => self.arithmetic("-", start, Number::checked_sub)?,
+                OpCode::Multiply => self.arithmetic("*", start, Number::checked_mul)?,
+                OpCode::Divide => self.arithmetic("/", start, Number::checked_div)?,
+                OpCode::Not => {
+                    let value = self.pop();
+                    self.stack.push(Value::Bool(!value.is_truthy()));
+                }
+                OpCode::Negate => match self.pop() {
+                    Value::Number(val) => match val.checked_neg() {
+                        Ok(negated) => self.stack.push(Value::Number(negated)),
+                        Err(err) => {
+                            return Err(RuntimeError::arithmetic(err, "-", self.span_at(start)))
+                        }
+                    },
+                    _ => {
+                        return Err(RuntimeError::OperandMustBeNumber {
+                            operator: "-".to_string(),
+                            span: self.span_at(start),
+                        })
+                    }
+                },
+                OpCode::Print => {
+                    let value = self.pop();
+                    writeln!(self.output, "{}", self.heap.display(&value))
+                        .map_err(|err| RuntimeError::Output(err.to_string()))?;
+                }
+                OpCode::Jump => {
+                    let offset = self.read_u16();
+                    self.frame_mut().ip += offset as usize;
+                }
+                OpCode::JumpIfFalse => {
+                    let offset = self.read_u16();
+                    if !self.peek(0).is_truthy() {
+                        self.frame_mut().ip += offset as usize;
+                    }
+                }
+                OpCode::Loop => {
+                    RuntimeError::check_interrupt(&self.interrupt, self.span_at(start))?;
+                    let offset = self.read_u16();
+                    self.frame_mut().ip -= offset as usize;
+                }
+                OpCode::Call => {
+                    let count = self.read_byte() as usize;
+                    let callee = self.peek(count).clone();
+                    RuntimeError::check_interrupt(&self.interrupt, self.span_at(start))?;
+                    self.call_value(callee, count, start)?;
+                }
+                OpCode::Closure => {
+                    let function = match self.read_constant() {
+                        Constant::Function(function) => function,
+                        constant => panic!("expected a function constant, found {:?}", constant),
+                    };
+
+                    let mut upvalues = Vec::with_capacity(function.upvalue_count);
+                    for _ in 0..function.upvalue_count {
+                        let is_local = self.read_byte() == 1;
+                        let index = self.read_byte() as usize;
+                        upvalues.push(if is_local {
+                            self.capture_upvalue(self.frame().base + index)
+                        } else {
+                            self.heap.closure(self.frame().closure).upvalues[index]
+                        });
+                    }
+
+                    let closure = self.alloc(Object::Closure(Closure { function, upvalues }));
+                    self.stack.push(Value::Object(closure));
+                }
+                OpCode::CloseUpvalue => {
+                    self.close_upvalues(self.stack.len() - 1);
+                    self.pop();
+                }
+                OpCode::Return => {
+                    let result = self.pop();
+                    let Some(frame) = self.frames.pop() else {
+                        return Ok(());
+                    };
+                    self.close_upvalues(frame.base);
+                    self.stack.truncate(frame.base);
+
+                    if self.frames.is_empty() {
+                        return Ok(());
+                    }
+                    self.stack.push(result);
+                }
+                OpCode::Class => {
+                    let name = self.read_string();
+                    let class = self.alloc(Object::Class(Class {
+                        name,
+                        methods: HashMap::new(),
+                    }));
+                    self.stack.push(Value::Object(class));
+                }
+                OpCode::Inherit => {
+                    let superclass = match self.peek(1) {
+                        Value::Object(reference) => match self.heap.get(*reference) {
+                            Object::Class(superclass) => superclass.methods.clone(),
+                            _ => {
+                                return Err(RuntimeError::SuperclassNotAClass {
+                                    span: self.span_at(start),
+                                })
+                            }
+                        },
+                        _ => {
+                            return Err(RuntimeError::SuperclassNotAClass {
+                                span: self.span_at(start),
+                            })
+                        }
+                    };
+
+                    // methods are copied down, the subclass overrides them later
+                    if let Value::Object(subclass) = self.pop() {
+                        self.heap.class_mut(subclass).methods = superclass;
+                    }
+                }
+                OpCode::Method => {
+                    let name = self.read_string();
+                    let method = self.pop();
+                    if let (Value::Object(class), Value::Object(method)) = (self.peek(0), method) {
+                        let class = *class;
+                        self.heap.class_mut(class).methods.insert(name, method);
+                    }
+                }
+            }
+        }
+    }
+
+    fn call_value(&mut self, callee: Value, count: usize, at: usize) -> Result<(), RuntimeError> {
+        let reference = match callee {
+            Value::Native(native) => return self.call_native(native, count, at),
+            Value::Object(reference) => reference,
+            _ => {
+                return Err(RuntimeError::NotCallable {
+                    span: self.span_at(at),
+                })
+            }
+        };
+
+        let slot = self.stack.len() - count - 1;
+        match self.heap.get(reference) {
+            Object::Closure(_) => self.call(reference, count, at),
+            Object::BoundMethod(bound) => {
+                let method = bound.method;
+                self.stack[slot] = bound.receiver.clone();
+                self.call(method, count, at)
+            }
+            Object::Class(class) => {
+                let initializer = class.methods.get("init").copied();
+                let instance = self.alloc(Object::Instance(Instance {
+                    class: reference,
+                    fields: HashMap::new(),
+                }));
+                self.stack[slot] = Value::Object(instance);
+
+                match initializer {
+                    Some(initializer) => self.call(initializer, count, at),
+                    None if count != 0 => Err(RuntimeError::ArityMismatch {
+                        expected: 0,
+                        got: count,
+                        span: self.span_at(at),
+                    }),
+                    None => Ok(()),
+                }
+            }
+            Object::Upvalue(_) | Object::Instance(_) => Err(RuntimeError::NotCallable {
+                span: self.span_at(at),
+            }),
+        }
+    }
+
+    fn call(&mut self, closure: ObjRef, count: usize, at: usize) -> Result<(), RuntimeError> {
+        let function = Rc::clone(&self.heap.closure(closure).function);
+        if count != function.arity {
+            return Err(RuntimeError::ArityMismatch {
+                expected: function.arity,
+                got: count,
+                span: self.span_at(at),
+            });
+        }
+        if self.frames.len() == FRAMES_MAX {
+            return Err(RuntimeError::StackOverflow {
+                span: self.span_at(at),
+            });
+        }
+
+        self.frames.push(CallFrame {
+            closure,
+            function,
+            ip: 0,
+            base: self.stack.len() - count - 1,
+        });
+        Ok(())
+    }
+
+    fn call_native(&mut self, native: Native, count: usize, at: usize) -> Result<(), RuntimeError> {
+        if count != native.arity {
+            return Err(RuntimeError::ArityMismatch {
+                expected: native.arity,
+                got: count,
+                span: self.span_at(at),
+            });
+        }
+
+        let arguments = self
+            .stack
+            .split_off(self.stack.len() - count)
+            .iter()
+            .map(NativeValue::from)
+            .collect::<Vec<_>>();
+        let result = native.call(&arguments).into();
+        self.pop();
+        self.stack.push(result);
+        Ok(())
+    }
+
+    /// Looks up `name` on `class` and binds it to `receiver`
+    fn bind_method(
+        &mut self,
+        class: ObjRef,
+        name: &Rc<str>,
+        receiver: Value,
+        at: usize,
+    ) -> Result<Value, RuntimeError> {
+        let method = match self.heap.get(class) {
+            Object::Class(class) => class.methods.get(name).copied(),
+            _ => None,
+        };
+        let Some(method) = method else {
+            return Err(RuntimeError::UndefinedProperty {
+                name: name.to_string(),
+                span: self.span_at(at),
+            });
+        };
+
+        let bound = self.alloc(Object::BoundMethod(BoundMethod { receiver, method }));
+        Ok(Value::Object(bound))
+    }
+
+    /// Allocates `object`, collecting garbage first when the heap asks for it
+    fn alloc(&mut self, object: Object) -> ObjRef {
+        if self.heap.should_collect() {
+            self.heap.mark_references(&object);
+            self.collect_garbage();
+        }
+        self.heap.alloc(object)
+    }
+
+    /// Marks everything the running program can still reach and frees the
+    /// rest
+    fn collect_garbage(&mut self) {
+        for value in self.stack.iter().chain(self.globals.values()) {
+            self.heap.mark_value(value);
+        }
+        for frame in &self.frames {
+            self.heap.mark(frame.closure);
+        }
+        for upvalue in &self.open_upvalues {
+            self.heap.mark(*upvalue);
+        }
+        self.heap.collect();
+    }
+
+    /// Returns the upvalue for stack `slot`, reusing an open one if a
+    /// closure already captured it
+    fn capture_upvalue(&mut self, slot: usize) -> ObjRef {
+        let existing = self
+            .open_upvalues
+            .iter()
+            .find(|upvalue| matches!(self.heap.get(**upvalue), Object::Upvalue(Upvalue::Open(open)) if *open == slot));
+        if let Some(upvalue) = existing {
+            return *upvalue;
+        }
+
+        let upvalue = self.alloc(Object::Upvalue(Upvalue::Open(slot)));
+        self.open_upvalues.push(upvalue);
+        upvalue
+    }
+
+    /// Moves every variable from stack slot `from` upwards into its upvalue
+    fn close_upvalues(&mut self, from: usize) {
+        let stack = &self.stack;
+        let heap = &mut self.heap;
+        self.open_upvalues.retain(|upvalue| {
+            let upvalue = heap.upvalue_mut(*upvalue);
+            match upvalue {
+                Upvalue::Open(slot) if *slot >= from => {
+                    *upvalue = Upvalue::Closed(stack[*slot].clone());
+                    false
+                }
+                _ => true,
+            }
+        });
+    }
+
+    fn arithmetic(
+        &mut self,
+        operator: &str,
+        at: usize,
+        op: fn(Number, Number) -> Result<Number, ArithmeticError>,
+    ) -> Result<(), RuntimeError> {
+        let (l, r) = self.number_operands(operator, at)?;
+        let value =
+            op(l, r).map_err(|err| RuntimeError::arithmetic(err, operator, self.span_at(at)))?;
+        self.stack.push(Value::Number(value));
+        Ok(())
+    }
+
+    fn comparison(
+        &mut self,
+        operator: &str,
+        at: usize,
+        op: fn(Number, Number) -> bool,
+    ) -> Result<(), RuntimeError> {
+        let (l, r) = self.number_operands(operator, at)?;
+        self.stack.push(Value::Bool(op(l, r)));
+        Ok(())
+    }
+
+    fn number_operands(
+        &mut self,
+        operator: &str,
+        at: usize,
+    ) -> Result<(Number, Number), RuntimeError> {
+        match self.pop_pair() {
+            (Value::Number(l), Value::Number(r)) => Ok((l, r)),
+            _ => Err(RuntimeError::OperandsMustBeNumbers {
+                operator: operator.to_string(),
+                span: self.span_at(at),
+            }),
+        }
+    }
+
+    /// Source span of the instruction at offset `at` in the current frame,
+    /// only looked up once an error needs it
+    fn span_at(&self, at: usize) -> Span {
+        self.frame().function.chunk.span_at(at)
+    }
+
+    fn frame(&self) -> &CallFrame {
+        &self.frames[self.frames.len() - 1]
+    }
+
+    fn frame_mut(&mut self) -> &mut CallFrame {
+        let last = self.frames.len() - 1;
+        &mut self.frames[last]
+    }
+
+    fn frame_upvalue(&mut self) -> ObjRef {
+        let index = self.read_byte() as usize;
+        self.heap.closure(self.frame().closure).upvalues[index]
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        let frame = self.frame_mut();
+        let byte = frame.function.chunk.code[frame.ip];
+        frame.ip += 1;
+        byte
+    }
+
+    fn read_u16(&mut self) -> u16 {
+        let frame = self.frame_mut();
+        let value = frame.function.chunk.read_u16(frame.ip);
+        frame.ip += 2;
+        value
+    }
+
+    fn read_constant(&mut self) -> Constant {
+        let index = self.read_u16() as usize;
+        self.frame().function.chunk.constants[index].clone()
+    }
+
+    fn read_string(&mut self) -> Rc<str> {
+        match self.read_constant() {
+            Constant::String(name) => name,
+            constant => panic!("expected a string constant, found {:?}", constant),
+        }
+    }
+
+    fn peek(&self, distance: usize) -> &Value {
+        &self.stack[self.stack.len() - 1 - distance]
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().unwrap_or(Value::Nil)
+    }
+
+    /// Pops the two operands of a binary instruction, left one first
+    fn pop_pair(&mut self) -> (Value, Value) {
+        let right = self.pop();
+        let left = self.pop();
+        (left, right)
+    }
+}
+
+fn undefined_variable(name: &str, span: Span) -> RuntimeError {
+    RuntimeError::UndefinedVariable {
+        name: name.to_string(),
+        span,
+    }
+}